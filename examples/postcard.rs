@@ -0,0 +1,74 @@
+//! Build a tree on the host, encode it with postcard and decode it again the
+//! way a device would after reading the bytes from flash.
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use serde::{Deserialize, Serialize};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, LeafNode,
+    alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Threshold {
+    Above(i16),
+    Below(i16),
+}
+impl ComputableLeaf for Threshold {
+    type LeafContext = i16;
+    type LeafOutput = bool;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        match self {
+            Self::Above(limit) => context > limit,
+            Self::Below(limit) => context < limit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Gate {
+    All,
+    Any,
+}
+impl<BA, LA, BM, LM> ComputableBranch<Threshold, BA, LA, BM, LM> for Gate
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Threshold>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = i16;
+    type BranchOutput = bool;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Threshold>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Threshold, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        match self {
+            Self::All => controls.compute_all(context),
+            Self::Any => controls.compute_any(context),
+        }
+    }
+}
+make_tree_aliases!(SensorTree, Gate, Threshold, u8, u8);
+
+fn main() {
+    // Host side: build the tree and encode it.
+    let tree: SensorTree = ConstructableTreeBranch::new(Gate::Any)
+        .with_leaf(Threshold::Below(-10))
+        .with_branch(
+            ConstructableTreeBranch::new(Gate::All)
+                .with_leaf(Threshold::Above(20))
+                .with_leaf(Threshold::Below(30)),
+        )
+        .to_tree()
+        .unwrap();
+    let bytes = postcard::to_allocvec(&tree).unwrap();
+    println!("encoded tree into {} bytes: {:02x?}", bytes.len(), bytes);
+
+    // Device side: `from_bytes` only needs the byte slice, so this part works
+    // in `no_std` builds without an allocator.
+    let decoded: SensorTree = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, tree);
+    for temperature in [-20, 0, 25, 40] {
+        println!("{temperature}: {}", decoded.compute(&temperature));
+    }
+}
@@ -7,13 +7,29 @@
 //! sent to the microcontroller. Direct construction of a [`Tree`] is not
 //! encouraged because removal of elements can be quite unperformant.
 extern crate alloc;
+use core::cmp::Ordering;
+use core::fmt::{Display, Write};
 use core::marker::PhantomData;
 
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
+use core::cell::RefCell;
 use mask_tracked_array::{Mask, MaskTrackedArray};
 
-use crate::{BranchNode, ChildrenMask, LeafNode, TinyExprTree};
+use crate::{
+    BranchControls, BranchNode, ChildrenMask, ComputableBranch, ComputableLeaf, ComputeHooks,
+    LeafNode, TinyExprTree, sealed,
+};
+/// A tree built on the host before being packed into a [`TinyExprTree`].
+///
+/// Equality and hashing are derived, so two branches that compare equal with
+/// [`PartialEq`] always hash the same and can be used as map keys. The order of
+/// `sub_branches` and `leaves` is significant: it decides the slot order
+/// [`ConstructableTreeBranch::to_tree`] assigns, so branches holding the same
+/// children in a different order are neither equal nor guaranteed to hash the
+/// same.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConstructableTreeBranch<B, L> {
     pub sub_branches: Vec<Box<ConstructableTreeBranch<B, L>>>,
@@ -24,6 +40,111 @@ pub struct ConstructableTreeBranch<B, L> {
 pub struct ConstructableTreeLeaf<L> {
     pub value: L,
 }
+impl<L> ConstructableTreeLeaf<L> {
+    pub fn new(value: L) -> Self {
+        Self { value }
+    }
+}
+
+/// A branch or leaf value yielded by [`ConstructableTreeBranch::iter_dfs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeRef<'a, B, L> {
+    Branch(&'a B),
+    Leaf(&'a L),
+}
+
+enum DfsPending<'a, B, L> {
+    Branch(&'a ConstructableTreeBranch<B, L>),
+    Leaves(core::slice::Iter<'a, ConstructableTreeLeaf<L>>),
+}
+
+struct DfsIter<'a, B, L> {
+    stack: Vec<DfsPending<'a, B, L>>,
+}
+
+impl<'a, B, L> Iterator for DfsIter<'a, B, L> {
+    type Item = NodeRef<'a, B, L>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                DfsPending::Branch(branch) => {
+                    self.stack.push(DfsPending::Leaves(branch.leaves.iter()));
+                    self.stack.extend(
+                        branch
+                            .sub_branches
+                            .iter()
+                            .rev()
+                            .map(|branch| DfsPending::Branch(&**branch)),
+                    );
+                    return Some(NodeRef::Branch(&branch.value));
+                }
+                DfsPending::Leaves(mut leaves) => {
+                    if let Some(leaf) = leaves.next() {
+                        self.stack.push(DfsPending::Leaves(leaves));
+                        return Some(NodeRef::Leaf(&leaf.value));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An owned branch or leaf value yielded by iterating over a
+/// [`ConstructableTreeBranch`] by value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OwnedNode<B, L> {
+    Branch(B),
+    Leaf(L),
+}
+
+enum IntoIterPending<B, L> {
+    Branch(ConstructableTreeBranch<B, L>),
+    Leaves(vec::IntoIter<ConstructableTreeLeaf<L>>),
+}
+
+/// Consuming iterator over a [`ConstructableTreeBranch`], in the same
+/// depth-first pre-order as [`ConstructableTreeBranch::iter_dfs`].
+pub struct IntoIter<B, L> {
+    stack: Vec<IntoIterPending<B, L>>,
+}
+
+impl<B, L> Iterator for IntoIter<B, L> {
+    type Item = OwnedNode<B, L>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                IntoIterPending::Branch(branch) => {
+                    self.stack
+                        .push(IntoIterPending::Leaves(branch.leaves.into_iter()));
+                    self.stack.extend(
+                        branch
+                            .sub_branches
+                            .into_iter()
+                            .rev()
+                            .map(|branch| IntoIterPending::Branch(*branch)),
+                    );
+                    return Some(OwnedNode::Branch(branch.value));
+                }
+                IntoIterPending::Leaves(mut leaves) => {
+                    if let Some(leaf) = leaves.next() {
+                        self.stack.push(IntoIterPending::Leaves(leaves));
+                        return Some(OwnedNode::Leaf(leaf.value));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<B, L> IntoIterator for ConstructableTreeBranch<B, L> {
+    type Item = OwnedNode<B, L>;
+    type IntoIter = IntoIter<B, L>;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            stack: vec![IntoIterPending::Branch(self)],
+        }
+    }
+}
 
 struct AccumulatingVisitor<B, L, BA, LA, BM, LM>
 where
@@ -32,73 +153,473 @@ where
 {
     branches: BA,
     leaves: LA,
+    required_branches: usize,
+    required_leaves: usize,
     _phantom: PhantomData<(B, L, BM, LM)>,
 }
 
+impl<B, L, BA, LA, BM, LM> AccumulatingVisitor<B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    fn branch_capacity_error(&self) -> ConstructionError {
+        ConstructionError::InsufficientBranchCapacity {
+            required: self.required_branches,
+            available: BM::MAX_SELECTIONS as usize,
+        }
+    }
+    fn leaf_capacity_error(&self) -> ConstructionError {
+        ConstructionError::InsufficientLeafCapacity {
+            required: self.required_leaves,
+            available: LM::MAX_SELECTIONS as usize,
+        }
+    }
+    /// Push `leaves` in order and return their mask. `shared` may return an
+    /// already filled slot to reuse instead of pushing a copy.
+    fn push_leaves(
+        &mut self,
+        leaves: Vec<ConstructableTreeLeaf<L>>,
+        shared: impl Fn(&LA, &LeafNode<L>, LM) -> Option<usize>,
+    ) -> Result<LM, ConstructionError> {
+        leaves
+            .into_iter()
+            .try_fold(LM::NONE_SELECTED, |mask, leaf| {
+                let leaf = LeafNode { leaf: leaf.value };
+                let index = match shared(&self.leaves, &leaf, mask) {
+                    Some(index) => index,
+                    None => self
+                        .leaves
+                        .push(leaf)
+                        .map_err(|_| self.leaf_capacity_error())?,
+                };
+                Ok(mask | (LM::ONE_SELECTED << index))
+            })
+    }
+    /// Push `branch` into a sibling set with mask `siblings` and return its
+    /// bit. `shared` may return an already filled slot to reuse instead.
+    fn push_branch(
+        &mut self,
+        branch: BranchNode<B, BM, LM>,
+        siblings: BM,
+        shared: impl Fn(&BA, &BranchNode<B, BM, LM>, BM) -> Option<usize>,
+    ) -> Result<BM, ConstructionError> {
+        if let Some(index) = shared(&self.branches, &branch, siblings) {
+            return Ok(BM::ONE_SELECTED << index);
+        }
+        self.branches
+            .push(branch)
+            .map(|index| BM::ONE_SELECTED << index)
+            .map_err(|_| self.branch_capacity_error())
+    }
+}
+
+/// Never shares a slot, so every node gets its own.
+fn no_shared_slot<A, T, M>(_: &A, _: &T, _: M) -> Option<usize> {
+    None
+}
+
+/// Find a filled slot holding a node equal to `node` that can join the
+/// siblings in `mask`. Children are computed in slot order, so the slot has to
+/// come after every sibling already in `mask`. This also keeps a repeated
+/// child from collapsing into a single bit.
+fn equal_shared_slot<A, T, M>(array: &A, node: &T, mask: M) -> Option<usize>
+where
+    A: MaskTrackedArray<T, MaskType = M>,
+    T: PartialEq,
+    M: Mask,
+{
+    array
+        .iter_filled_indices()
+        .zip(array.iter())
+        .find(|&(index, existing)| (M::ONE_SELECTED << index) > mask && existing == node)
+        .map(|(index, _)| index)
+}
+
+/// A branch whose sub-branches are still being pushed by
+/// [`ConstructableTreeBranch::to_tree_with_max_depth`].
+struct VisitFrame<B, L, BM> {
+    value: B,
+    sub_branches: vec::IntoIter<Box<ConstructableTreeBranch<B, L>>>,
+    leaves: Vec<ConstructableTreeLeaf<L>>,
+    branch_mask: BM,
+    depth: usize,
+}
+
+impl<B, L, BM: Mask> VisitFrame<B, L, BM> {
+    fn new(branch: ConstructableTreeBranch<B, L>, depth: usize) -> Self {
+        Self {
+            value: branch.value,
+            sub_branches: branch.sub_branches.into_iter(),
+            leaves: branch.leaves,
+            branch_mask: BM::NONE_SELECTED,
+            depth,
+        }
+    }
+}
+
+/// Reasons a [`ConstructableTreeBranch`] could not be turned into a
+/// [`TinyExprTree`]. More reasons may be added later, so matches on this need
+/// a wildcard arm.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ConstructionError {
-    InsufficientBranchCapacity,
-    InsufficientLeafCapacity,
+    /// The branch array ran out of slots. `required` is the total number of
+    /// branch slots the tree needs and `available` the number the mask type
+    /// provides, so the first branch that did not fit would have gone in slot
+    /// `available`, and `required - available` more slots are needed. The
+    /// root branch is stored outside the array, so `required` does not count
+    /// it.
+    InsufficientBranchCapacity { required: usize, available: usize },
+    /// The leaf array ran out of slots. Like with
+    /// [`ConstructionError::InsufficientBranchCapacity`], `required` is the
+    /// total the tree needs, not the count at which packing stopped.
+    InsufficientLeafCapacity { required: usize, available: usize },
+    /// A branch was nested deeper than the allowed maximum. The root branch is
+    /// at depth 1 and leaves do not add to the depth. `found` is the depth of
+    /// the first branch visited past the limit.
+    DepthExceeded { found: usize, max: usize },
+    /// A branch had neither sub-branches nor leaves. Only reported by
+    /// [`ConstructableTreeBranch::to_tree_checked`]. `depth` is the depth of
+    /// the first empty branch found, with the root at depth 1.
+    EmptyBranch { depth: usize },
+}
+
+impl core::fmt::Display for ConstructionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InsufficientBranchCapacity {
+                required,
+                available,
+            } => write!(
+                f,
+                "insufficient branch capacity: tree needs {required} branch slots but only {available} are available"
+            ),
+            Self::InsufficientLeafCapacity {
+                required,
+                available,
+            } => write!(
+                f,
+                "insufficient leaf capacity: tree needs {required} leaf slots but only {available} are available"
+            ),
+            Self::DepthExceeded { found, max } => write!(
+                f,
+                "maximum depth exceeded: found a branch at depth {found} but the maximum is {max}"
+            ),
+            Self::EmptyBranch { depth } => {
+                write!(f, "empty branch: a branch at depth {depth} has no children")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ConstructionError {}
+
+/// Reasons a path given to [`ConstructableTreeBranch::insert_branch_at`] and
+/// friends did not point at a valid position.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// The path was empty, but it needs at least the position to insert or
+    /// remove at.
+    Empty,
+    /// The index at `path[depth]` was past the children of the branch reached
+    /// so far, or did not select the right kind of child.
+    OutOfRange { depth: usize },
+}
+
+impl core::fmt::Display for PathError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty path"),
+            Self::OutOfRange { depth } => {
+                write!(f, "path index at depth {depth} is out of range")
+            }
+        }
+    }
+}
+
+impl core::error::Error for PathError {}
+
+/// Slot usage reported by [`ConstructableTreeBranch::to_tree_with_stats`]. The
+/// root branch is stored outside the branch array, so it is not counted in
+/// `branches_used`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStats {
+    pub branches_used: usize,
+    pub leaves_used: usize,
+    pub branch_capacity: usize,
+    pub leaf_capacity: usize,
+}
+
+impl TreeStats {
+    /// Branch slots still free.
+    pub fn spare_branches(&self) -> usize {
+        self.branch_capacity - self.branches_used
+    }
+    /// Leaf slots still free.
+    pub fn spare_leaves(&self) -> usize {
+        self.leaf_capacity - self.leaves_used
+    }
+}
+
+/// Per-node metadata split off by
+/// [`ConstructableTreeBranch::to_tree_with_meta`]. `branches` and `leaves` are
+/// indexed by the slot the node was packed into, so a slot reported at
+/// runtime can be mapped back to, for example, a line in a config file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TreeMeta<BMeta, LMeta> {
+    /// Metadata of the root branch, which is stored outside the branch array.
+    pub root: BMeta,
+    pub branches: Vec<BMeta>,
+    pub leaves: Vec<LMeta>,
 }
 
 impl<B, L> ConstructableTreeBranch<B, L> {
     pub fn branch_count(&self) -> usize {
-        self.sub_branches
-            .iter()
-            .map(|branch| branch.branch_count())
-            .sum::<usize>()
-            + 1
+        let mut count = 0;
+        self.for_each_branch(|_| count += 1);
+        count
     }
     pub fn leaf_count(&self) -> usize {
-        self.leaves.len()
-            + self
-                .sub_branches
+        let mut count = 0;
+        self.for_each_branch(|branch| count += branch.leaves.len());
+        count
+    }
+    /// Number of branches in this tree, including this one, whose value
+    /// satisfies `pred`.
+    pub fn count_branches<F: Fn(&B) -> bool>(&self, pred: F) -> usize {
+        let mut count = 0;
+        self.for_each_branch(|branch| count += pred(&branch.value) as usize);
+        count
+    }
+    /// Number of leaves in this tree whose value satisfies `pred`.
+    pub fn count_leaves<F: Fn(&L) -> bool>(&self, pred: F) -> usize {
+        let mut count = 0;
+        self.for_each_branch(|branch| {
+            count += branch
+                .leaves
                 .iter()
-                .map(|branch| branch.leaf_count())
-                .sum::<usize>()
+                .filter(|leaf| pred(&leaf.value))
+                .count()
+        });
+        count
+    }
+    /// Number of branch levels in this tree. A branch with no sub-branches has
+    /// depth 1 whether or not it has leaves, and every other branch is one
+    /// deeper than its deepest sub-branch. This is the same depth that
+    /// [`ConstructableTreeBranch::to_tree_with_max_depth`] checks against.
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((branch, depth)) = stack.pop() {
+            deepest = deepest.max(depth);
+            stack.extend(
+                branch
+                    .sub_branches
+                    .iter()
+                    .map(|branch| (&**branch, depth + 1)),
+            );
+        }
+        deepest
     }
-    fn visit<BA, LA, BM, LM>(
+    /// Iterate over every branch and leaf value in depth-first pre-order. Each
+    /// branch is yielded before its sub-branches, which come before its own
+    /// leaves.
+    pub fn iter_dfs(&self) -> impl Iterator<Item = NodeRef<'_, B, L>> {
+        DfsIter {
+            stack: vec![DfsPending::Branch(self)],
+        }
+    }
+    /// Consume the tree and collect every leaf value in the order
+    /// [`ConstructableTreeBranch::iter_dfs`] visits them: a branch's
+    /// sub-branches come before its own leaves. This is also the order of the
+    /// leaf slots [`ConstructableTreeBranch::to_tree`] assigns.
+    pub fn into_leaves(self) -> Vec<L> {
+        self.into_iter()
+            .filter_map(|node| match node {
+                OwnedNode::Leaf(leaf) => Some(leaf),
+                OwnedNode::Branch(_) => None,
+            })
+            .collect()
+    }
+    /// Call `f` on this branch and every branch below it without recursing, so
+    /// very deep trees do not overflow the stack.
+    fn for_each_branch(&self, mut f: impl FnMut(&Self)) {
+        let mut stack = vec![self];
+        while let Some(branch) = stack.pop() {
+            f(branch);
+            stack.extend(branch.sub_branches.iter().map(|branch| &**branch));
+        }
+    }
+    /// Check that this tree fits in branch and leaf masks of type `BM` and
+    /// `LM` without doing the conversion. The root branch is stored outside the
+    /// branch array, so a tree with `BM::MAX_SELECTIONS + 1` branches still
+    /// fits. If this returns `Ok`, [`ConstructableTreeBranch::to_tree`] will
+    /// not fail with a capacity error for the same mask types.
+    pub fn check_capacity<BM, LM>(&self) -> Result<(), ConstructionError>
+    where
+        BM: Mask,
+        LM: Mask,
+    {
+        let required_branches = self.branch_count() - 1;
+        let required_leaves = self.leaf_count();
+        if required_branches > BM::MAX_SELECTIONS as usize {
+            return Err(ConstructionError::InsufficientBranchCapacity {
+                required: required_branches,
+                available: BM::MAX_SELECTIONS as usize,
+            });
+        }
+        if required_leaves > LM::MAX_SELECTIONS as usize {
+            return Err(ConstructionError::InsufficientLeafCapacity {
+                required: required_leaves,
+                available: LM::MAX_SELECTIONS as usize,
+            });
+        }
+        Ok(())
+    }
+    /// Pack this tree into the fixed size arrays of a [`TinyExprTree`].
+    ///
+    /// Slots are assigned only from the shape and child order of the tree, and
+    /// this order is part of the API. Both arrays are filled from slot 0
+    /// upwards without gaps, walking the tree depth-first:
+    ///
+    /// 1. A branch's sub-branches are walked first, in the order they were
+    ///    added.
+    /// 2. Then the branch's own leaves take the next leaf slots, in the order
+    ///    they were added.
+    /// 3. Then the branch itself takes the next branch slot, so branches are
+    ///    numbered in post-order. The root is stored outside the branch array
+    ///    and takes no slot.
+    ///
+    /// For example, `Or { And { a, b }, c }` puts `a` and `b` in leaf slots 0
+    /// and 1, `c` in leaf slot 2 and `And` in branch slot 0. Converting equal
+    /// trees therefore always gives identical masks and slot layouts, so
+    /// serialized trees are reproducible.
+    /// [`ConstructableTreeBranch::to_tree_with_meta`] can carry node ids
+    /// through the conversion to find the slot each node ended up in.
+    ///
+    /// The branch and leaf counts are checked with
+    /// [`ConstructableTreeBranch::check_capacity`] before any node is moved
+    /// into the arrays, so a tree that does not fit fails straight away rather
+    /// than partway through packing.
+    pub fn to_tree<BA, LA, BM, LM>(
         self,
-        visitor: &mut AccumulatingVisitor<B, L, BA, LA, BM, LM>,
-    ) -> Result<BM, ConstructionError>
+    ) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
     where
         BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
         LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
         BM: Mask,
         LM: Mask,
     {
-        let branch_mask: Result<BM, ConstructionError> = self
-            .sub_branches
-            .into_iter()
-            .map(|branch| branch.visit(visitor))
-            .try_fold(<BM as Mask>::NONE_SELECTED, |acc, value| {
-                Ok(acc | value.map_err(|_| ConstructionError::InsufficientBranchCapacity)?)
-            });
-        let leaf_mask: Result<LM, ConstructionError> = self
-            .leaves
-            .into_iter()
-            .map(|leaf| visitor.leaves.push(LeafNode { leaf: leaf.value }))
-            .try_fold(<LM as Mask>::NONE_SELECTED, |acc, value| {
-                Ok(acc
-                    | (<LM as Mask>::ONE_SELECTED
-                        << value.map_err(|_| ConstructionError::InsufficientLeafCapacity)?))
-            });
-        let branch_mask = branch_mask?;
-        let leaf_mask = leaf_mask?;
-        let branch_node = BranchNode {
-            branch: self.value,
-            mask: ChildrenMask {
-                branch_mask,
-                leaf_mask,
-            },
+        self.to_tree_with_max_depth(usize::MAX)
+    }
+    /// Same as [`ConstructableTreeBranch::to_tree`], but first sorts the leaves
+    /// of every branch with `cmp`, so the slot layout does not depend on the
+    /// order leaves were added in. The sort is stable. Leaves are also computed
+    /// in the sorted order, which matters for branches that care about the
+    /// order of their children.
+    pub fn to_tree_sorted<BA, LA, BM, LM>(
+        mut self,
+        cmp: impl Fn(&L, &L) -> Ordering,
+    ) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
+    where
+        BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+        LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+        BM: Mask,
+        LM: Mask,
+    {
+        let mut stack = vec![&mut self];
+        while let Some(branch) = stack.pop() {
+            branch.leaves.sort_by(|a, b| cmp(&a.value, &b.value));
+            stack.extend(branch.sub_branches.iter_mut().map(|branch| &mut **branch));
+        }
+        self.to_tree()
+    }
+    /// Same as [`ConstructableTreeBranch::to_tree`], but fails with
+    /// [`ConstructionError::DepthExceeded`] if branches are nested deeper than
+    /// `max`. [`TinyExprTree::compute`] recurses once per branch level, so this
+    /// bounds the stack usage of computing the resulting tree.
+    pub fn to_tree_with_max_depth<BA, LA, BM, LM>(
+        self,
+        max: usize,
+    ) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
+    where
+        BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+        LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+        BM: Mask,
+        LM: Mask,
+    {
+        self.fitting::<BM, LM>()?
+            .pack(max, true, no_shared_slot, no_shared_slot)
+    }
+    /// Same as [`ConstructableTreeBranch::to_tree`], but identical subtrees and
+    /// leaves are stored once and every parent points at the same slot, which
+    /// saves mask bits when a tree repeats itself. Computing is read only, so
+    /// a shared node behaves as if each parent had its own copy, and
+    /// [`TinyExprTree::to_constructable`] expands it back out.
+    ///
+    /// A node is only shared when that keeps each branch's children in their
+    /// original order and distinct, so some duplicates may still get their own
+    /// slot. Writing to a shared slot through [`TinyExprTree::leaf_mut`] or
+    /// [`TinyExprTree::branch_mut`] changes it for every parent. The `required`
+    /// count in a capacity error is the size of the tree without sharing.
+    pub fn to_tree_dedup<BA, LA, BM, LM>(
+        self,
+    ) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
+    where
+        B: PartialEq,
+        L: PartialEq,
+        BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+        LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+        BM: Mask,
+        LM: Mask,
+    {
+        self.pack(usize::MAX, true, equal_shared_slot, equal_shared_slot)
+    }
+    /// Same as [`ConstructableTreeBranch::to_tree`], but fails with
+    /// [`ConstructionError::EmptyBranch`] if any branch, including the root,
+    /// has neither sub-branches nor leaves.
+    pub fn to_tree_checked<BA, LA, BM, LM>(
+        self,
+    ) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
+    where
+        BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+        LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+        BM: Mask,
+        LM: Mask,
+    {
+        self.fitting::<BM, LM>()?
+            .pack(usize::MAX, false, no_shared_slot, no_shared_slot)
+    }
+    /// Return `self` if [`ConstructableTreeBranch::check_capacity`] passes.
+    /// Otherwise the tree is taken apart one branch at a time, since dropping
+    /// a deeply nested tree in one go would recurse once per level.
+    fn fitting<BM, LM>(self) -> Result<Self, ConstructionError>
+    where
+        BM: Mask,
+        LM: Mask,
+    {
+        let error = match self.check_capacity::<BM, LM>() {
+            Ok(()) => return Ok(self),
+            Err(error) => error,
         };
-        let this_index = visitor.branches.push(branch_node);
-        this_index
-            .map(|index| BM::ONE_SELECTED << index)
-            .map_err(|_| ConstructionError::InsufficientBranchCapacity)
+        let mut stack = vec![self];
+        while let Some(mut branch) = stack.pop() {
+            stack.extend(branch.sub_branches.drain(..).map(|branch| *branch));
+        }
+        Err(error)
     }
-    pub fn to_tree<BA, LA, BM, LM>(
+    fn pack<BA, LA, BM, LM>(
         self,
+        max: usize,
+        allow_empty: bool,
+        shared_leaf: impl Fn(&LA, &LeafNode<L>, LM) -> Option<usize> + Copy,
+        shared_branch: impl Fn(&BA, &BranchNode<B, BM, LM>, BM) -> Option<usize> + Copy,
     ) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
     where
         BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
@@ -106,35 +627,48 @@ impl<B, L> ConstructableTreeBranch<B, L> {
         BM: Mask,
         LM: Mask,
     {
+        if max < 1 {
+            return Err(ConstructionError::DepthExceeded { found: 1, max });
+        }
         let mut visitor = AccumulatingVisitor {
             _phantom: PhantomData,
             branches: BA::new(),
             leaves: LA::new(),
+            required_branches: self.branch_count() - 1,
+            required_leaves: self.leaf_count(),
         };
-        let branch_mask: Result<BM, ConstructionError> = self
-            .sub_branches
-            .into_iter()
-            .map(|branch| branch.visit(&mut visitor))
-            .try_fold(BM::NONE_SELECTED, |acc, value| {
-                Ok(acc | value.map_err(|_| ConstructionError::InsufficientBranchCapacity)?)
-            });
-        let leaf_mask: Result<LM, ConstructionError> = self
-            .leaves
-            .into_iter()
-            .map(|leaf| visitor.leaves.push(LeafNode { leaf: leaf.value }))
-            .try_fold(LM::NONE_SELECTED, |acc, value| {
-                Ok(acc
-                    | (LM::ONE_SELECTED
-                        << value.map_err(|_| ConstructionError::InsufficientLeafCapacity)?))
-            });
-        let branch_mask = branch_mask?;
-        let leaf_mask = leaf_mask?;
-        let branch_node = BranchNode {
-            branch: self.value,
-            mask: ChildrenMask {
-                branch_mask,
-                leaf_mask,
-            },
+        // Branches are pushed in post-order: all sub-branches first, then the
+        // branch's own leaves, then the branch itself. The root is never pushed.
+        let mut stack = vec![VisitFrame::new(self, 1)];
+        let branch_node = loop {
+            let frame = stack.last_mut().expect("root frame is popped last");
+            if let Some(branch) = frame.sub_branches.next() {
+                let depth = frame.depth + 1;
+                if depth > max {
+                    return Err(ConstructionError::DepthExceeded { found: depth, max });
+                }
+                stack.push(VisitFrame::new(*branch, depth));
+                continue;
+            }
+            let frame = stack.pop().expect("stack is not empty");
+            if !allow_empty && frame.leaves.is_empty() && frame.branch_mask == BM::NONE_SELECTED {
+                return Err(ConstructionError::EmptyBranch { depth: frame.depth });
+            }
+            let leaf_mask = visitor.push_leaves(frame.leaves, shared_leaf)?;
+            let branch_node = BranchNode {
+                branch: frame.value,
+                mask: ChildrenMask {
+                    branch_mask: frame.branch_mask,
+                    leaf_mask,
+                },
+            };
+            match stack.last_mut() {
+                Some(parent) => {
+                    parent.branch_mask = parent.branch_mask
+                        | visitor.push_branch(branch_node, parent.branch_mask, shared_branch)?
+                }
+                None => break branch_node,
+            }
         };
         Ok(TinyExprTree {
             inner: crate::TreeInner {
@@ -145,6 +679,28 @@ impl<B, L> ConstructableTreeBranch<B, L> {
             root: branch_node,
         })
     }
+    /// Same as [`ConstructableTreeBranch::to_tree`], but also reports how many
+    /// slots of each array the tree uses, so callers packing several trees
+    /// can tell whether more will fit.
+    #[allow(clippy::type_complexity)]
+    pub fn to_tree_with_stats<BA, LA, BM, LM>(
+        self,
+    ) -> Result<(TinyExprTree<B, L, BA, LA, BM, LM>, TreeStats), ConstructionError>
+    where
+        BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+        LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+        BM: Mask,
+        LM: Mask,
+    {
+        let tree: TinyExprTree<B, L, BA, LA, BM, LM> = self.to_tree()?;
+        let stats = TreeStats {
+            branches_used: tree.inner.branches.len() as usize,
+            leaves_used: tree.inner.leaves.len() as usize,
+            branch_capacity: BM::MAX_SELECTIONS as usize,
+            leaf_capacity: LM::MAX_SELECTIONS as usize,
+        };
+        Ok((tree, stats))
+    }
     pub fn new(root: B) -> Self {
         Self {
             sub_branches: Vec::new(),
@@ -152,10 +708,854 @@ impl<B, L> ConstructableTreeBranch<B, L> {
             leaves: Vec::new(),
         }
     }
+    /// Make a new branch with value `root` and `children` as its sub-branches,
+    /// in order. Useful for combining separately built trees under one root.
+    pub fn merge_under<I: IntoIterator<Item = Self>>(root: B, children: I) -> Self {
+        Self {
+            sub_branches: children.into_iter().map(Box::new).collect(),
+            value: root,
+            leaves: Vec::new(),
+        }
+    }
     pub fn add_branch(&mut self, branch: Self) {
         self.sub_branches.push(Box::new(branch));
     }
+    /// Add a sub-branch and return a reference to it so it can be filled in
+    /// afterwards.
+    pub fn add_branch_mut(&mut self, branch: Self) -> &mut Self {
+        self.sub_branches.push(Box::new(branch));
+        let last = self.sub_branches.len() - 1;
+        &mut self.sub_branches[last]
+    }
+    /// Add a sub-branch with value `value` and fill it in with `f`, so nested
+    /// trees can be written as nested closures.
+    pub fn add_branch_with(&mut self, value: B, f: impl FnOnce(&mut Self)) {
+        f(self.add_branch_mut(Self::new(value)));
+    }
     pub fn add_leaf(&mut self, leaf: L) {
-        self.leaves.push(ConstructableTreeLeaf { value: leaf });
+        self.leaves.push(ConstructableTreeLeaf::new(leaf));
+    }
+    /// Like [`ConstructableTreeBranch::add_leaf`], but takes an already built
+    /// [`ConstructableTreeLeaf`].
+    pub fn add_leaf_node(&mut self, leaf: ConstructableTreeLeaf<L>) {
+        self.leaves.push(leaf);
+    }
+    /// Call `f` on every leaf in the tree, for example to fill in placeholder
+    /// leaves right before conversion. Leaves are visited in the order
+    /// [`ConstructableTreeBranch::to_tree`] assigns their slots: a branch's
+    /// sub-branches are visited before its own leaves.
+    pub fn bind_leaves(&mut self, mut f: impl FnMut(&mut L)) {
+        let mut stack = vec![(&mut self.leaves, self.sub_branches.iter_mut())];
+        while let Some((_, sub_branches)) = stack.last_mut() {
+            if let Some(branch) = sub_branches.next() {
+                let branch = &mut **branch;
+                stack.push((&mut branch.leaves, branch.sub_branches.iter_mut()));
+                continue;
+            }
+            let (leaves, _) = stack.pop().expect("stack is not empty");
+            leaves.iter_mut().for_each(|leaf| f(&mut leaf.value));
+        }
+    }
+    /// Add every leaf yielded by `leaves`, in order.
+    pub fn extend_leaves<I: IntoIterator<Item = L>>(&mut self, leaves: I) {
+        self.leaves
+            .extend(leaves.into_iter().map(ConstructableTreeLeaf::new));
+    }
+    /// Keep only the direct leaves of this branch for which `f` returns `true`,
+    /// like [`Vec::retain`]. Leaves of sub-branches are not visited.
+    pub fn retain_leaves<F: FnMut(&L) -> bool>(&mut self, mut f: F) {
+        self.leaves.retain(|leaf| f(&leaf.value));
+    }
+    /// Keep only the direct sub-branches of this branch for which `f` returns
+    /// `true`, like [`Vec::retain`]. This only operates one level deep; removing
+    /// a sub-branch also removes everything below it.
+    pub fn retain_branches<F: FnMut(&ConstructableTreeBranch<B, L>) -> bool>(&mut self, mut f: F) {
+        self.sub_branches.retain(|branch| f(branch));
+    }
+    /// Insert `branch` at `path`. Every element but the last picks a
+    /// sub-branch to descend into. The last is the position among the
+    /// children of the branch reached, counting sub-branches first and then
+    /// leaves like [`TinyExprTree::compute`] does, so it has to be at most
+    /// the number of sub-branches there.
+    pub fn insert_branch_at(&mut self, path: &[usize], branch: Self) -> Result<(), PathError> {
+        let (&index, parent) = path.split_last().ok_or(PathError::Empty)?;
+        let parent = self.branch_at_path_mut(parent)?;
+        if index > parent.sub_branches.len() {
+            return Err(PathError::OutOfRange {
+                depth: path.len() - 1,
+            });
+        }
+        parent.sub_branches.insert(index, Box::new(branch));
+        Ok(())
+    }
+    /// Insert `leaf` at `path`, like [`ConstructableTreeBranch::insert_branch_at`].
+    /// Since leaves come after sub-branches, the last index has to be between
+    /// the number of sub-branches and the number of children of the branch
+    /// reached.
+    pub fn insert_leaf_at(&mut self, path: &[usize], leaf: L) -> Result<(), PathError> {
+        let (&index, parent) = path.split_last().ok_or(PathError::Empty)?;
+        let parent = self.branch_at_path_mut(parent)?;
+        let index = index
+            .checked_sub(parent.sub_branches.len())
+            .filter(|index| *index <= parent.leaves.len())
+            .ok_or(PathError::OutOfRange {
+                depth: path.len() - 1,
+            })?;
+        parent
+            .leaves
+            .insert(index, ConstructableTreeLeaf::new(leaf));
+        Ok(())
+    }
+    /// Remove and return the child at `path`, indexed like
+    /// [`ConstructableTreeBranch::insert_branch_at`]. A removed sub-branch
+    /// takes everything below it along.
+    pub fn remove_at(&mut self, path: &[usize]) -> Result<OwnedNode<Self, L>, PathError> {
+        let (&index, parent) = path.split_last().ok_or(PathError::Empty)?;
+        let parent = self.branch_at_path_mut(parent)?;
+        if index < parent.sub_branches.len() {
+            return Ok(OwnedNode::Branch(*parent.sub_branches.remove(index)));
+        }
+        let index = index - parent.sub_branches.len();
+        if index < parent.leaves.len() {
+            return Ok(OwnedNode::Leaf(parent.leaves.remove(index).value));
+        }
+        Err(PathError::OutOfRange {
+            depth: path.len() - 1,
+        })
+    }
+    /// The branch reached by descending into the sub-branch at each index of
+    /// `path` in turn.
+    fn branch_at_path_mut(&mut self, path: &[usize]) -> Result<&mut Self, PathError> {
+        path.iter()
+            .enumerate()
+            .try_fold(self, |branch, (depth, &index)| {
+                branch
+                    .sub_branches
+                    .get_mut(index)
+                    .map(|branch| &mut **branch)
+                    .ok_or(PathError::OutOfRange { depth })
+            })
+    }
+    /// Flatten chains of associative operators into single wide branches. Any
+    /// sub-branch whose value `is_associative_with` its parent's is removed
+    /// and its children are moved into the parent, repeatedly, so
+    /// `a AND (b AND (c AND d))` becomes one `AND` with four children. This
+    /// lowers the depth [`TinyExprTree::compute`] recurses to and frees the
+    /// mask bits of the removed branches.
+    ///
+    /// The absorbed sub-branches of a branch take the place of the branch they
+    /// came from, but absorbed leaves are placed after the parent's own
+    /// leaves, so operators marked as associative should also not depend on
+    /// the order of their children. The tree is walked without recursion.
+    pub fn rebalance(&mut self, is_associative_with: impl Fn(&B, &B) -> bool) {
+        let mut stack = vec![self];
+        while let Some(branch) = stack.pop() {
+            // Pending children in reverse, so the next one is at the end.
+            let mut pending: Vec<_> = branch.sub_branches.drain(..).rev().collect();
+            let mut absorbed_leaves = Vec::new();
+            while let Some(mut sub_branch) = pending.pop() {
+                if is_associative_with(&branch.value, &sub_branch.value) {
+                    pending.extend(sub_branch.sub_branches.drain(..).rev());
+                    absorbed_leaves.append(&mut sub_branch.leaves);
+                } else {
+                    branch.sub_branches.push(sub_branch);
+                }
+            }
+            branch.leaves.append(&mut absorbed_leaves);
+            stack.extend(branch.sub_branches.iter_mut().map(|branch| &mut **branch));
+        }
+    }
+    /// Transform every branch and leaf value, keeping the shape and child order
+    /// of the tree exactly the same. Each branch value is mapped before its
+    /// sub-branches, which are mapped before its leaves.
+    pub fn map<B2, L2>(
+        self,
+        mut fb: impl FnMut(B) -> B2,
+        mut fl: impl FnMut(L) -> L2,
+    ) -> ConstructableTreeBranch<B2, L2> {
+        self.map_inner(&mut fb, &mut fl)
+    }
+    fn map_inner<B2, L2, FB, FL>(self, fb: &mut FB, fl: &mut FL) -> ConstructableTreeBranch<B2, L2>
+    where
+        FB: FnMut(B) -> B2,
+        FL: FnMut(L) -> L2,
+    {
+        let value = fb(self.value);
+        let sub_branches = self
+            .sub_branches
+            .into_iter()
+            .map(|branch| Box::new(branch.map_inner(fb, fl)))
+            .collect();
+        let leaves = self
+            .leaves
+            .into_iter()
+            .map(|leaf| ConstructableTreeLeaf {
+                value: fl(leaf.value),
+            })
+            .collect();
+        ConstructableTreeBranch {
+            sub_branches,
+            value,
+            leaves,
+        }
+    }
+    /// Fallible version of [`ConstructableTreeBranch::map`]. Values are mapped
+    /// in the same order and the first error is returned as soon as it occurs.
+    pub fn try_map<B2, L2, E>(
+        self,
+        mut fb: impl FnMut(B) -> Result<B2, E>,
+        mut fl: impl FnMut(L) -> Result<L2, E>,
+    ) -> Result<ConstructableTreeBranch<B2, L2>, E> {
+        self.try_map_inner(&mut fb, &mut fl)
+    }
+    fn try_map_inner<B2, L2, E, FB, FL>(
+        self,
+        fb: &mut FB,
+        fl: &mut FL,
+    ) -> Result<ConstructableTreeBranch<B2, L2>, E>
+    where
+        FB: FnMut(B) -> Result<B2, E>,
+        FL: FnMut(L) -> Result<L2, E>,
+    {
+        let value = fb(self.value)?;
+        let sub_branches = self
+            .sub_branches
+            .into_iter()
+            .map(|branch| branch.try_map_inner(fb, fl).map(Box::new))
+            .collect::<Result<Vec<_>, E>>()?;
+        let leaves = self
+            .leaves
+            .into_iter()
+            .map(|leaf| fl(leaf.value).map(|value| ConstructableTreeLeaf { value }))
+            .collect::<Result<Vec<_>, E>>()?;
+        Ok(ConstructableTreeBranch {
+            sub_branches,
+            value,
+            leaves,
+        })
+    }
+    /// Chaining version of [`ConstructableTreeBranch::add_branch`].
+    pub fn with_branch(mut self, branch: Self) -> Self {
+        self.add_branch(branch);
+        self
+    }
+    /// Chaining version of [`ConstructableTreeBranch::add_leaf`].
+    pub fn with_leaf(mut self, leaf: L) -> Self {
+        self.add_leaf(leaf);
+        self
+    }
+}
+
+/// An empty branch with value `B::default()`, same as
+/// `ConstructableTreeBranch::new(B::default())`. Unlike a derived impl, `L`
+/// does not need to implement [`Default`].
+impl<B: Default, L> Default for ConstructableTreeBranch<B, L> {
+    fn default() -> Self {
+        Self::new(B::default())
+    }
+}
+
+/// Build a tree with value `root` at the root, filled in by `f`, and convert it
+/// with [`ConstructableTreeBranch::to_tree`] in one call.
+///
+/// ```
+/// # use tiny_expr_tree::alloc_gen::build_tree;
+/// # use tiny_expr_tree::TinyExprTree;
+/// # use tiny_expr_tree::{BranchNode, LeafNode};
+/// # type BA = tiny_expr_tree::MaskTrackedArrayU8<BranchNode<&'static str, u8, u8>>;
+/// # type LA = tiny_expr_tree::MaskTrackedArrayU8<LeafNode<u32>>;
+/// let tree: TinyExprTree<_, _, BA, LA, u8, u8> = build_tree("all", |root| {
+///     root.add_leaf(1);
+///     root.add_branch_with("any", |branch| {
+///         branch.add_leaf(2);
+///         branch.add_leaf(3);
+///     });
+/// })
+/// .unwrap();
+/// assert_eq!(tree.leaf_count(), 3);
+/// ```
+pub fn build_tree<B, L, BA, LA, BM, LM>(
+    root: B,
+    f: impl FnOnce(&mut ConstructableTreeBranch<B, L>),
+) -> Result<TinyExprTree<B, L, BA, LA, BM, LM>, ConstructionError>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    let mut construction = ConstructableTreeBranch::new(root);
+    f(&mut construction);
+    construction.to_tree()
+}
+
+impl<B, L, BMeta, LMeta> ConstructableTreeBranch<(B, BMeta), (L, LMeta)> {
+    /// Same as [`ConstructableTreeBranch::to_tree`] for a tree whose values are
+    /// paired with metadata, such as ids or source spans. The metadata is kept
+    /// out of the packed tree and returned separately, indexed by slot.
+    #[allow(clippy::type_complexity)]
+    pub fn to_tree_with_meta<BA, LA, BM, LM>(
+        self,
+    ) -> Result<(TinyExprTree<B, L, BA, LA, BM, LM>, TreeMeta<BMeta, LMeta>), ConstructionError>
+    where
+        BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+        LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+        BM: Mask,
+        LM: Mask,
+    {
+        let (construction, meta) = self.split_meta();
+        Ok((construction.to_tree()?, meta))
+    }
+    /// Separate the metadata from the values, collecting it in the order
+    /// [`ConstructableTreeBranch::to_tree`] assigns slots: a branch's
+    /// sub-branches first, then its own leaves, then the branch itself.
+    fn split_meta(self) -> (ConstructableTreeBranch<B, L>, TreeMeta<BMeta, LMeta>) {
+        let mut branch_meta = Vec::new();
+        let mut leaf_meta = Vec::new();
+        let frame = |branch: Self| {
+            (
+                branch.value,
+                branch.sub_branches.into_iter(),
+                Vec::new(),
+                branch.leaves,
+            )
+        };
+        let mut stack = vec![frame(self)];
+        loop {
+            let (_, sub_branches, _, _) = stack.last_mut().expect("root frame is popped last");
+            if let Some(branch) = sub_branches.next() {
+                stack.push(frame(*branch));
+                continue;
+            }
+            let ((value, meta), _, sub_branches, leaves) = stack.pop().expect("stack is not empty");
+            let leaves = leaves
+                .into_iter()
+                .map(|leaf| {
+                    let (value, meta) = leaf.value;
+                    leaf_meta.push(meta);
+                    ConstructableTreeLeaf::new(value)
+                })
+                .collect();
+            let branch = ConstructableTreeBranch {
+                sub_branches,
+                value,
+                leaves,
+            };
+            match stack.last_mut() {
+                Some((_, _, siblings, _)) => {
+                    branch_meta.push(meta);
+                    siblings.push(Box::new(branch));
+                }
+                None => {
+                    let meta = TreeMeta {
+                        root: meta,
+                        branches: branch_meta,
+                        leaves: leaf_meta,
+                    };
+                    return (branch, meta);
+                }
+            }
+        }
+    }
+}
+
+impl<B, L> Extend<L> for ConstructableTreeBranch<B, L> {
+    fn extend<I: IntoIterator<Item = L>>(&mut self, iter: I) {
+        self.extend_leaves(iter);
+    }
+}
+
+impl<B: Display, L: Display> ConstructableTreeBranch<B, L> {
+    /// Render the tree as a Graphviz digraph, with branches drawn as ellipses
+    /// and leaves as boxes. Nodes are numbered in depth-first pre-order, so the
+    /// same tree always produces the same output.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        let mut next_id = 0;
+        self.write_dot(&mut dot, &mut next_id);
+        dot.push_str("}\n");
+        dot
+    }
+    /// Render the tree as indented text, one node per line. Each level is
+    /// indented by two more spaces than its parent, and children are listed
+    /// sub-branches first, then leaves, in the order they are stored.
+    pub fn pretty(&self) -> String {
+        let mut text = String::new();
+        self.write_pretty(&mut text, 0);
+        text
+    }
+    fn write_pretty(&self, text: &mut String, depth: usize) {
+        let _ = writeln!(text, "{:indent$}{}", "", self.value, indent = depth * 2);
+        for branch in &self.sub_branches {
+            branch.write_pretty(text, depth + 1);
+        }
+        for leaf in &self.leaves {
+            let _ = writeln!(
+                text,
+                "{:indent$}{}",
+                "",
+                leaf.value,
+                indent = (depth + 1) * 2
+            );
+        }
+    }
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        write_dot_node(dot, id, &self.value, "ellipse");
+        for branch in &self.sub_branches {
+            let child = branch.write_dot(dot, next_id);
+            let _ = writeln!(dot, "    n{id} -> n{child};");
+        }
+        for leaf in &self.leaves {
+            let child = *next_id;
+            *next_id += 1;
+            write_dot_node(dot, child, &leaf.value, "box");
+            let _ = writeln!(dot, "    n{id} -> n{child};");
+        }
+        id
+    }
+}
+
+fn write_dot_node(dot: &mut String, id: usize, value: &impl Display, shape: &str) {
+    let mut label = String::new();
+    let _ = write!(label, "{value}");
+    let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+    let _ = writeln!(dot, "    n{id} [label=\"{label}\", shape={shape}];");
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: Clone,
+    L: Clone,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// Rebuild the [`ConstructableTreeBranch`] this tree was made from. Children
+    /// are read in ascending slot order, which is the order
+    /// [`ConstructableTreeBranch::to_tree`] assigned them in, so converting the
+    /// result back with `to_tree` yields an identical tree.
+    ///
+    /// A tree made by [`ConstructableTreeBranch::to_tree_dedup`] is rebuilt
+    /// with a separate copy of every shared node for each of its parents, so
+    /// converting it back with `to_tree` can need more slots than the tree has
+    /// and fail. Use `to_tree_dedup` again to share them.
+    pub fn to_constructable(&self) -> ConstructableTreeBranch<B, L> {
+        self.decompile_branch(&self.root)
+    }
+    /// Copy the subtree rooted at the branch in slot `branch_index` into a tree
+    /// of its own, with that branch as the new root. The reachable nodes are
+    /// moved to the lowest slots of fresh arrays, keeping their relative
+    /// order, and every mask is remapped to match. A node shared by
+    /// [`ConstructableTreeBranch::to_tree_dedup`] gets a single new slot, so it
+    /// stays shared. Returns `None` if the slot is empty.
+    pub fn subtree(&self, branch_index: u32) -> Option<Self> {
+        let branch = self.inner.branches.get_ref(branch_index as usize)?;
+        // Collect every reachable slot, visiting each shared branch once.
+        let mut branch_mask = BM::NONE_SELECTED;
+        let mut leaf_mask = branch.mask.leaf_mask;
+        let mut pending = branch.mask.branch_mask;
+        while pending != BM::NONE_SELECTED {
+            let slot = pending.trailing_zeros() as usize;
+            let bit = BM::ONE_SELECTED << slot;
+            branch_mask = branch_mask | bit;
+            let mask = unsafe { self.inner.branches.get_unchecked_ref(slot) }.mask;
+            leaf_mask = leaf_mask | mask.leaf_mask;
+            pending = (pending & !bit) | (mask.branch_mask & !branch_mask);
+        }
+        let remap = |node: &BranchNode<B, BM, LM>| BranchNode {
+            branch: node.branch.clone(),
+            mask: ChildrenMask {
+                branch_mask: pack_mask(node.mask.branch_mask, branch_mask),
+                leaf_mask: pack_mask(node.mask.leaf_mask, leaf_mask),
+            },
+        };
+        let branches = self
+            .inner
+            .branches
+            .iter_mask(branch_mask)
+            .map(remap)
+            .collect();
+        let leaves = self.inner.leaves.iter_mask(leaf_mask).cloned().collect();
+        Some(TinyExprTree {
+            root: remap(branch),
+            inner: crate::TreeInner {
+                branches,
+                leaves,
+                _phantom: PhantomData,
+            },
+        })
+    }
+    fn decompile_branch(&self, node: &BranchNode<B, BM, LM>) -> ConstructableTreeBranch<B, L> {
+        let sub_branches = self
+            .inner
+            .branches
+            .iter_mask(node.mask.branch_mask)
+            .map(|branch| Box::new(self.decompile_branch(branch)))
+            .collect();
+        let leaves = self
+            .inner
+            .leaves
+            .iter_mask(node.mask.leaf_mask)
+            .map(|leaf| ConstructableTreeLeaf {
+                value: leaf.leaf.clone(),
+            })
+            .collect();
+        ConstructableTreeBranch {
+            sub_branches,
+            value: node.branch.clone(),
+            leaves,
+        }
+    }
+}
+
+/// Move the bits of `mask` to the slots they get when only the slots in
+/// `kept` are packed into the lowest slots, in order. A slot's new position is
+/// the number of kept slots below it.
+fn pack_mask<M: Mask>(mask: M, kept: M) -> M {
+    let mut packed = M::NONE_SELECTED;
+    let mut rest = mask;
+    while rest != M::NONE_SELECTED {
+        let slot = rest.trailing_zeros() as usize;
+        let bit = M::ONE_SELECTED << slot;
+        let new_slot = (kept & (bit - M::ONE_SELECTED)).count_ones() as usize;
+        packed = packed | M::ONE_SELECTED << new_slot;
+        rest = rest & !bit;
+    }
+    packed
+}
+
+/// Builds a [`ConstructableTreeBranch`] from a literal tree. A branch is its
+/// value followed by a brace block of comma separated children, and any child
+/// without a brace block is a leaf.
+///
+/// ```
+/// # use tiny_expr_tree::expr_tree;
+/// # use tiny_expr_tree::alloc_gen::ConstructableTreeBranch;
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanComparator { And, Or }
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanLeaf { True, False, InsertedValue }
+/// use BooleanComparator::*;
+/// use BooleanLeaf::*;
+/// let tree = expr_tree!(And { True, InsertedValue, Or { False } });
+/// let expected = ConstructableTreeBranch::new(And)
+///     .with_leaf(True)
+///     .with_leaf(InsertedValue)
+///     .with_branch(ConstructableTreeBranch::new(Or).with_leaf(False));
+/// assert_eq!(tree, expected);
+/// ```
+///
+/// Leaves and sub-branches keep their relative order, so the example is the
+/// same as adding `True` and `InsertedValue` as leaves and the `Or` branch as
+/// a sub-branch of `And`.
+#[macro_export]
+macro_rules! expr_tree {
+    (@branch [$($value:tt)+] { $($children:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut branch = $crate::alloc_gen::ConstructableTreeBranch::new($($value)+);
+        $crate::expr_tree!(@children branch [] $($children)*);
+        branch
+    }};
+    (@branch [$($value:tt)*] $next:tt $($rest:tt)+) => {
+        $crate::expr_tree!(@branch [$($value)* $next] $($rest)+)
+    };
+    (@children $branch:ident []) => {};
+    (@children $branch:ident [$($child:tt)+]) => {
+        $crate::expr_tree!(@child $branch [] $($child)+);
+    };
+    (@children $branch:ident [$($child:tt)*] , $($rest:tt)*) => {
+        $crate::expr_tree!(@children $branch [$($child)*]);
+        $crate::expr_tree!(@children $branch [] $($rest)*);
+    };
+    (@children $branch:ident [$($child:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr_tree!(@children $branch [$($child)* $next] $($rest)*);
+    };
+    (@child $branch:ident [$($value:tt)+] { $($children:tt)* }) => {
+        $branch.add_branch($crate::expr_tree!(@branch [$($value)+] { $($children)* }));
+    };
+    (@child $branch:ident [$($value:tt)*] $next:tt $($rest:tt)+) => {
+        $crate::expr_tree!(@child $branch [$($value)* $next] $($rest)+);
+    };
+    (@child $branch:ident [$($value:tt)*] $last:tt) => {
+        $branch.add_leaf($($value)* $last);
+    };
+    ($($tree:tt)+) => {
+        $crate::expr_tree!(@branch [] $($tree)+)
+    };
+}
+
+/// Builds a [`TinyExprTree`] from a literal tree written like in
+/// [`expr_tree!`], checking at compile time that it fits the branch and leaf
+/// masks. The masks are given first, followed by `;` and the tree.
+///
+/// ```
+/// # use tiny_expr_tree::{make_tree_aliases, static_tree};
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanComparator { And, Or }
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanLeaf { True, False, InsertedValue }
+/// # make_tree_aliases!(MiniTree, BooleanComparator, BooleanLeaf, u8, u16);
+/// use BooleanComparator::*;
+/// use BooleanLeaf::*;
+/// let tree: MiniTree = static_tree!(u8, u16; And { True, InsertedValue, Or { False } });
+/// assert_eq!(tree.leaf_count(), 3);
+/// ```
+///
+/// The check counts the branches and leaves written in the literal, so a tree
+/// with more sub-branches than `BM` has bits or more leaves than `LM` has bits
+/// fails `cargo build` instead of returning a [`ConstructionError`]. Only the
+/// shape of the literal is known at compile time: the tree itself is still
+/// built and packed at runtime, which needs the `alloc-gen` feature, and the
+/// mask types have to match the ones of the tree type being built. The depth
+/// is not checked.
+///
+/// Nine leaves do not fit a `u8` leaf mask:
+///
+/// ```compile_fail,E0080
+/// # use tiny_expr_tree::{make_tree_aliases, static_tree};
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanComparator { And, Or }
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanLeaf { True, False, InsertedValue }
+/// # make_tree_aliases!(TinyTree, BooleanComparator, BooleanLeaf, u8, u8);
+/// use BooleanComparator::*;
+/// use BooleanLeaf::*;
+/// let tree: TinyTree = static_tree!(u8, u8; And {
+///     True, False, True, False, Or { True, False, True, False, True },
+/// });
+/// ```
+#[macro_export]
+macro_rules! static_tree {
+    // Counts below the root, which is not stored in the branch array.
+    (@root $mode:ident { $($children:tt)* }) => {
+        $crate::static_tree!(@children $mode [] $($children)*)
+    };
+    (@root $mode:ident $next:tt $($rest:tt)+) => {
+        $crate::static_tree!(@root $mode $($rest)+)
+    };
+    (@children $mode:ident []) => { 0 };
+    (@children $mode:ident [$($child:tt)+]) => {
+        $crate::static_tree!(@child $mode $($child)+)
+    };
+    (@children $mode:ident [$($child:tt)*] , $($rest:tt)*) => {
+        $crate::static_tree!(@children $mode [$($child)*])
+            + $crate::static_tree!(@children $mode [] $($rest)*)
+    };
+    (@children $mode:ident [$($child:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::static_tree!(@children $mode [$($child)* $next] $($rest)*)
+    };
+    (@child branches { $($children:tt)* }) => {
+        1 + $crate::static_tree!(@children branches [] $($children)*)
+    };
+    (@child leaves { $($children:tt)* }) => {
+        $crate::static_tree!(@children leaves [] $($children)*)
+    };
+    (@child $mode:ident $next:tt $($rest:tt)+) => {
+        $crate::static_tree!(@child $mode $($rest)+)
+    };
+    (@child branches $last:tt) => { 0 };
+    (@child leaves $last:tt) => { 1 };
+    ($bm:ty, $lm:ty; $($tree:tt)+) => {{
+        const BRANCHES: usize = $crate::static_tree!(@root branches $($tree)+);
+        const LEAVES: usize = $crate::static_tree!(@root leaves $($tree)+);
+        const {
+            assert!(
+                BRANCHES <= <$bm>::BITS as usize,
+                "tree has more sub-branches than the branch mask has bits"
+            );
+            assert!(
+                LEAVES <= <$lm>::BITS as usize,
+                "tree has more leaves than the leaf mask has bits"
+            );
+        }
+        $crate::expr_tree!($($tree)+)
+            .to_tree()
+            .expect("capacity is checked at compile time")
+    }};
+}
+
+/// One output recorded by [`TinyExprTree::compute_traced`]. `index` is the
+/// slot of the node in the branch or leaf array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEntry<BO, LO = BO> {
+    Branch {
+        index: u32,
+        output: BO,
+    },
+    Leaf {
+        index: u32,
+        output: LO,
+    },
+    /// The root branch, which is not stored in the branch array. It is always
+    /// the last entry.
+    Root {
+        output: BO,
+    },
+}
+
+/// Collects [`TraceEntry`]s while [`TinyExprTree::compute_traced`] runs.
+struct Tracer<BO, LO> {
+    entries: RefCell<Vec<TraceEntry<BO, LO>>>,
+}
+
+impl<BO, LO> sealed::Sealed for &Tracer<BO, LO> {}
+impl<BO: Clone, L> ComputeHooks<BO, L> for &Tracer<BO, L::LeafOutput>
+where
+    L: ComputableLeaf<LeafOutput: Clone>,
+{
+    fn compute_branch(self, index: usize, compute: impl FnOnce() -> BO) -> BO {
+        let output = compute();
+        self.entries.borrow_mut().push(TraceEntry::Branch {
+            index: index as u32,
+            output: output.clone(),
+        });
+        output
+    }
+    fn compute_leaf(self, index: usize, compute: impl FnOnce() -> L::LeafOutput) -> L::LeafOutput {
+        let output = compute();
+        self.entries.borrow_mut().push(TraceEntry::Leaf {
+            index: index as u32,
+            output: output.clone(),
+        });
+        output
+    }
+}
+
+/// Collects leaf outputs with their slots while
+/// [`TinyExprTree::compute_with_leaves`] runs.
+struct LeafTracer<LO> {
+    leaves: RefCell<Vec<(u32, LO)>>,
+}
+
+impl<LO> sealed::Sealed for &LeafTracer<LO> {}
+impl<BO, L> ComputeHooks<BO, L> for &LeafTracer<L::LeafOutput>
+where
+    L: ComputableLeaf<LeafOutput: Clone>,
+{
+    fn compute_branch(self, _index: usize, compute: impl FnOnce() -> BO) -> BO {
+        compute()
+    }
+    fn compute_leaf(self, index: usize, compute: impl FnOnce() -> L::LeafOutput) -> L::LeafOutput {
+        let output = compute();
+        self.leaves
+            .borrow_mut()
+            .push((index as u32, output.clone()));
+        output
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM>,
+    B::BranchOutput: Clone,
+    L: ComputableLeaf,
+    L::LeafOutput: Clone,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// Same as [`TinyExprTree::compute`], but also returns the output of every
+    /// node that was computed, for debugging on the host. Entries are in the
+    /// order outputs were produced, so a branch comes after the children it
+    /// computed, and a child computed twice appears twice. Children a branch
+    /// never computes do not appear at all.
+    #[allow(clippy::type_complexity)]
+    pub fn compute_traced(
+        &self,
+        context: &B::BranchContext,
+    ) -> (
+        B::BranchOutput,
+        Vec<TraceEntry<B::BranchOutput, L::LeafOutput>>,
+    ) {
+        let tracer = Tracer {
+            entries: RefCell::new(Vec::new()),
+        };
+        let base_access = BranchControls::new(&self.inner, self.root.mask, &tracer);
+        let output = self.root.branch.compute(context, base_access);
+        let mut entries = tracer.entries.into_inner();
+        entries.push(TraceEntry::Root {
+            output: output.clone(),
+        });
+        (output, entries)
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM>,
+    L: ComputableLeaf,
+    L::LeafOutput: Clone,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// Same as [`TinyExprTree::compute`], but also returns the output of every
+    /// leaf that was computed with the slot of the leaf, to explain which
+    /// leaves led to the result. Leaves are in the order they were computed,
+    /// like in [`TinyExprTree::compute_traced`], so leaves that were skipped
+    /// do not appear and a leaf computed twice appears twice.
+    pub fn compute_with_leaves(
+        &self,
+        context: &B::BranchContext,
+    ) -> (B::BranchOutput, Vec<(u32, L::LeafOutput)>) {
+        let tracer = LeafTracer {
+            leaves: RefCell::new(Vec::new()),
+        };
+        let base_access = BranchControls::new(&self.inner, self.root.mask, &tracer);
+        let output = self.root.branch.compute(context, base_access);
+        let leaves = tracer.leaves.into_inner();
+        (output, leaves)
+    }
+}
+
+/// Deepest branch nesting produced by the [`arbitrary::Arbitrary`] impl of
+/// [`ConstructableTreeBranch`], counting the root as depth 1.
+#[cfg(feature = "arbitrary")]
+pub const ARBITRARY_MAX_DEPTH: usize = 4;
+/// Most leaves, and separately most sub-branches, the [`arbitrary::Arbitrary`]
+/// impl of [`ConstructableTreeBranch`] gives a single branch.
+#[cfg(feature = "arbitrary")]
+pub const ARBITRARY_MAX_CHILDREN: usize = 4;
+
+/// Generates trees of at most [`ARBITRARY_MAX_DEPTH`] levels with at most
+/// [`ARBITRARY_MAX_CHILDREN`] leaves and sub-branches per branch. Trees can
+/// still exceed the capacity of small masks, which is useful for fuzzing
+/// [`ConstructableTreeBranch::to_tree`].
+#[cfg(feature = "arbitrary")]
+impl<'a, B, L> arbitrary::Arbitrary<'a> for ConstructableTreeBranch<B, L>
+where
+    B: arbitrary::Arbitrary<'a>,
+    L: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Self::arbitrary_with_depth(u, ARBITRARY_MAX_DEPTH)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, B, L> ConstructableTreeBranch<B, L>
+where
+    B: arbitrary::Arbitrary<'a>,
+    L: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary_with_depth(
+        u: &mut arbitrary::Unstructured<'a>,
+        depth: usize,
+    ) -> arbitrary::Result<Self> {
+        let mut branch = Self::new(B::arbitrary(u)?);
+        for _ in 0..u.int_in_range(0..=ARBITRARY_MAX_CHILDREN)? {
+            branch.add_leaf(L::arbitrary(u)?);
+        }
+        if depth > 1 {
+            for _ in 0..u.int_in_range(0..=ARBITRARY_MAX_CHILDREN)? {
+                branch.add_branch(Self::arbitrary_with_depth(u, depth - 1)?);
+            }
+        }
+        Ok(branch)
     }
 }
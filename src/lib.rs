@@ -2,62 +2,230 @@
 #![doc=include_str!("../README.md")]
 use core::marker::PhantomData;
 
-use mask_tracked_array::{Mask, MaskTrackedArray};
+pub use mask_tracked_array::{
+    Mask, MaskTrackedArray, MaskTrackedArrayU8, MaskTrackedArrayU16, MaskTrackedArrayU32,
+    MaskTrackedArrayU64, MaskTrackedArrayU128,
+};
 
 #[cfg(feature = "alloc-gen")]
 pub mod alloc_gen;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[doc(hidden)]
+pub use paste as __paste;
 /// Should be implemented on branch node structs. Sub-branch/leaf access is
 /// provided by [`BranchControls`] so you should not hold references to
 /// branches and other items.
+///
+/// Each branch decides what context its children see. The `compute_*` methods
+/// of [`BranchControls`] take the context as an argument, so a branch can pass
+/// its own context on unchanged or narrow it first, for example to the part of
+/// a larger sensor array selected by its own value. The context may be unsized,
+/// such as `[i16]`, so a branch can narrow it to a sub-slice, and it is only
+/// borrowed for the duration of each call, independently of the tree. Leaves
+/// only ever see the context their parent branch passes to them.
+///
+/// `compute` is generic over the [`ComputeHooks`] of its controls, so that
+/// [`TinyExprTree::compute`] and the instrumented versions such as
+/// [`TinyExprTree::compute_with_fired_mask`] each get their own copy of it.
+/// Implementations only have to pass the bound on.
 pub trait ComputableBranch<L, BA, LA, BM, LM>
 where
     Self: Sized,
+    L: ComputableLeaf,
     BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
     LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
 {
     /// The context required to compute a branch node.
-    type BranchContext;
+    type BranchContext: ?Sized;
     /// Output from computing.
     type BranchOutput;
     /// Compute the value inside the branch node. [`BranchControls`] are available
     /// for accessing items in subnodes.
-    fn compute<'a>(
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, L>>(
         &self,
         context: &Self::BranchContext,
-        controls: BranchControls<'a, Self, L, BA, LA, BM, LM>,
+        controls: BranchControls<'a, Self, L, BA, LA, BM, LM, H>,
     ) -> Self::BranchOutput;
 }
 /// Should be implemented on leaf nodes structs.
 pub trait ComputableLeaf {
     /// Context required to compute a leaf node.
-    type LeafContext;
+    type LeafContext: ?Sized;
     /// Output from computing.
     type LeafOutput;
     /// Compute the value inside the leaf node using the context.
     fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput;
+    /// Same as [`ComputableLeaf::compute`], but also given `index`, the slot
+    /// of this leaf in the leaf array, which is its bit in the leaf masks.
+    /// [`BranchControls`] always computes leaves through this method, so
+    /// override it for leaves that behave differently depending on their
+    /// position. By default it ignores `index` and calls `compute`.
+    fn compute_indexed(&self, context: &Self::LeafContext, index: u32) -> Self::LeafOutput {
+        let _ = index;
+        self.compute(context)
+    }
+}
+
+/// Fallible version of [`ComputableBranch`]. Trees made of these branches are
+/// computed with [`TinyExprTree::try_compute`] and the `try_compute_*` methods
+/// of [`BranchControls`].
+/// Like in [`ComputableBranch`], the context may be unsized.
+pub trait TryComputableBranch<L, BA, LA, BM, LM>
+where
+    Self: Sized,
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    /// The context required to compute a branch node.
+    type BranchContext: ?Sized;
+    /// Output from computing.
+    type BranchOutput;
+    /// Error returned when computing fails.
+    type Error;
+    /// Try to compute the value inside the branch node. Errors from sub-nodes
+    /// can be propagated with `?`.
+    fn try_compute<'a>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, L, BA, LA, BM, LM>,
+    ) -> Result<Self::BranchOutput, Self::Error>;
+}
+/// Fallible version of [`ComputableLeaf`].
+pub trait TryComputableLeaf {
+    /// Context required to compute a leaf node.
+    type LeafContext: ?Sized;
+    /// Output from computing.
+    type LeafOutput;
+    /// Error returned when computing fails.
+    type Error;
+    /// Try to compute the value inside the leaf node using the context.
+    fn try_compute(&self, context: &Self::LeafContext) -> Result<Self::LeafOutput, Self::Error>;
+}
+
+/// Version of [`ComputableBranch`] whose context can be changed while
+/// computing, for example to log which leaves fired. Trees made of these
+/// branches are computed with [`TinyExprTree::compute_mut`] and the
+/// `compute_*_mut` methods of [`BranchControls`].
+/// The context may be unsized, so a branch can hand its children a mutable
+/// sub-slice.
+pub trait ComputableBranchMut<L, BA, LA, BM, LM>
+where
+    Self: Sized,
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    /// The context required to compute a branch node.
+    type BranchContext: ?Sized;
+    /// Output from computing.
+    type BranchOutput;
+    /// Compute the value inside the branch node. The context is handed on to
+    /// each child as it is computed.
+    fn compute_mut<'a>(
+        &self,
+        context: &mut Self::BranchContext,
+        controls: BranchControls<'a, Self, L, BA, LA, BM, LM>,
+    ) -> Self::BranchOutput;
+}
+/// Version of [`ComputableLeaf`] whose context can be changed while computing.
+pub trait ComputableLeafMut {
+    /// Context required to compute a leaf node.
+    type LeafContext: ?Sized;
+    /// Output from computing.
+    type LeafOutput;
+    /// Compute the value inside the leaf node using the context.
+    fn compute_mut(&self, context: &mut Self::LeafContext) -> Self::LeafOutput;
 }
 
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
 pub struct ChildrenMask<BM, LM> {
     pub branch_mask: BM,
     pub leaf_mask: LM,
 }
 
+#[cfg(feature = "bytemuck")]
+unsafe impl<BM: bytemuck::Zeroable, LM: bytemuck::Zeroable> bytemuck::Zeroable
+    for ChildrenMask<BM, LM>
+{
+}
+/// Only masks of the same width are [`bytemuck::Pod`], since two different
+/// widths would leave padding between or after the fields.
+#[cfg(feature = "bytemuck")]
+unsafe impl<M: bytemuck::Pod> bytemuck::Pod for ChildrenMask<M, M> {}
+
+impl<BM: Mask, LM: Mask> ChildrenMask<BM, LM> {
+    /// Children selected by either mask.
+    #[inline]
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            branch_mask: self.branch_mask | other.branch_mask,
+            leaf_mask: self.leaf_mask | other.leaf_mask,
+        }
+    }
+    /// Children selected by both masks.
+    #[inline]
+    pub fn intersection(self, other: Self) -> Self {
+        Self {
+            branch_mask: self.branch_mask & other.branch_mask,
+            leaf_mask: self.leaf_mask & other.leaf_mask,
+        }
+    }
+    /// Whether neither a sub-branch nor a leaf is selected.
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.branch_mask == BM::NONE_SELECTED && self.leaf_mask == LM::NONE_SELECTED
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BranchNode<B, BM, LM> {
     branch: B,
     mask: ChildrenMask<BM, LM>,
 }
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LeafNode<L> {
     leaf: L,
 }
 
+impl<B, BM, LM> BranchNode<B, BM, LM> {
+    /// A branch with value `branch` whose children are selected by `mask`, for
+    /// use with [`TinyExprTree::from_parts`].
+    pub fn new(branch: B, mask: ChildrenMask<BM, LM>) -> Self {
+        Self { branch, mask }
+    }
+    /// The branch value.
+    pub fn value(&self) -> &B {
+        &self.branch
+    }
+    /// The mask selecting this branch's sub-branches and leaves.
+    pub fn mask(&self) -> ChildrenMask<BM, LM>
+    where
+        BM: Copy,
+        LM: Copy,
+    {
+        self.mask
+    }
+}
+impl<L> LeafNode<L> {
+    /// A leaf with value `leaf`, for use with [`TinyExprTree::from_parts`].
+    pub fn new(leaf: L) -> Self {
+        Self { leaf }
+    }
+    /// The leaf value.
+    pub fn value(&self) -> &L {
+        &self.leaf
+    }
+}
+
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 struct TreeInner<B, L, BA, LA, BM, LM>
 where
     BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
@@ -65,13 +233,63 @@ where
 {
     branches: BA,
     leaves: LA,
-    _phantom: PhantomData<(B, L, BM)>,
+    _phantom: PhantomData<(B, L, BM, LM)>,
+}
+/// Iterate over the indices selected in `mask` from the highest to the lowest.
+fn iter_mask_indices_rev<M: Mask>(mut mask: M) -> impl Iterator<Item = usize> {
+    core::iter::from_fn(move || {
+        if mask == M::NONE_SELECTED {
+            return None;
+        }
+        let index = M::MAX_SELECTIONS - 1 - mask.leading_zeros();
+        mask = mask ^ (M::ONE_SELECTED << index as usize);
+        Some(index as usize)
+    })
+}
+
+/// Copy every filled slot of `array` into a new array at the same index.
+/// [`MaskTrackedArray`]s do not implement [`Clone`] themselves.
+fn clone_array<T: Clone, A: MaskTrackedArray<T>>(array: &A) -> A {
+    array
+        .iter_filled_indices()
+        .zip(array.iter().cloned())
+        .collect()
+}
+
+impl<B, L, BA, LA, BM, LM> Clone for TreeInner<B, L, BA, LA, BM, LM>
+where
+    B: Clone,
+    L: Clone,
+    BM: Clone,
+    LM: Clone,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            branches: clone_array(&self.branches),
+            leaves: clone_array(&self.leaves),
+            _phantom: PhantomData,
+        }
+    }
 }
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 /// A tiny tree suitable for microcontroller use. This struct is not directly
 /// constructable and you should use [`alloc_gen::ConstructableTreeBranch`]s
 /// instead on the host computer.
+///
+/// Two trees are equal when their root branches are equal and their branch
+/// and leaf arrays have the same masks and equal values in every filled slot.
+/// Trees with the same logical shape but different slot assignments are not
+/// equal.
+///
+/// The root is always a branch. It is stored outside the branch array and
+/// takes no slot, so an expression that is a single leaf is a
+/// [trivial](TinyExprTree::is_trivial) tree whose root branch has just that
+/// leaf. A leaf cannot be the root because [`TinyExprTree::compute`] takes the
+/// branch context and returns the branch output, which may be different types
+/// from the leaf's.
 pub struct TinyExprTree<B, L, BA, LA, BM, LM>
 where
     BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
@@ -80,27 +298,272 @@ where
     root: BranchNode<B, BM, LM>,
     inner: TreeInner<B, L, BA, LA, BM, LM>,
 }
+
+/// The backing arrays do not need to be [`Clone`]; their filled slots are
+/// copied into new arrays at the same indices.
+impl<B, L, BA, LA, BM, LM> Clone for TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: Clone,
+    L: Clone,
+    BM: Clone,
+    LM: Clone,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
 #[derive(Debug)]
 /// Provides compute actions for [`ComputableBranch`]es and access to
 /// sub-branches and leaves.
-pub struct BranchControls<'a, B, L, BA, LA, BM, LM>
+pub struct BranchControls<'a, B, L, BA, LA, BM, LM, H = NoHooks>
 where
     BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
     LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
 {
     inner_reference: &'a TreeInner<B, L, BA, LA, BM, LM>,
     mask: ChildrenMask<BA::MaskType, LA::MaskType>,
+    depth: usize,
+    /// Run around every child computed through the `compute_*` methods.
+    hooks: H,
 }
 
-impl<'a, B, L, BA, LA, BM, LM> BranchControls<'a, B, L, BA, LA, BM, LM>
+// Written out because a derive would require `B` and `L` to be `Copy` too.
+impl<B, L, BA, LA, BM, LM, H> Clone for BranchControls<'_, B, L, BA, LA, BM, LM, H>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Copy,
+    LM: Copy,
+    H: Copy,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<B, L, BA, LA, BM, LM, H> Copy for BranchControls<'_, B, L, BA, LA, BM, LM, H>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Copy,
+    LM: Copy,
+    H: Copy,
+{
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Runs around every branch and leaf that the `compute_*` methods of
+/// [`BranchControls`] compute, for the versions of [`TinyExprTree::compute`]
+/// that record or reuse outputs. `BO` is the branch output type.
+///
+/// This trait is sealed. Branches only see it as the bound on
+/// [`ComputableBranch::compute`], and plain computing uses [`NoHooks`], which
+/// the optimizer removes entirely.
+pub trait ComputeHooks<BO, L: ComputableLeaf>: Copy + sealed::Sealed {
+    /// Whether the hooks record or change anything while computing, so that
+    /// every child must be computed through them on the calling thread.
+    #[doc(hidden)]
+    const STATEFUL: bool = true;
+    /// The leaf context passed to [`TinyExprTree::compute_split`], if any.
+    fn leaf_context(&self) -> Option<&L::LeafContext> {
+        None
+    }
+    /// Produce the output of the sub-branch in slot `index`, usually by
+    /// calling `compute`.
+    fn compute_branch(self, index: usize, compute: impl FnOnce() -> BO) -> BO;
+    /// Produce the output of the leaf in slot `index`, usually by calling
+    /// `compute`.
+    fn compute_leaf(self, index: usize, compute: impl FnOnce() -> L::LeafOutput) -> L::LeafOutput;
+}
+
+/// The [`ComputeHooks`] of [`TinyExprTree::compute`], which compute every
+/// child directly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoHooks;
+
+impl sealed::Sealed for NoHooks {}
+impl<BO, L: ComputableLeaf> ComputeHooks<BO, L> for NoHooks {
+    const STATEFUL: bool = false;
+    #[inline(always)]
+    fn compute_branch(self, _: usize, compute: impl FnOnce() -> BO) -> BO {
+        compute()
+    }
+    #[inline(always)]
+    fn compute_leaf(self, _: usize, compute: impl FnOnce() -> L::LeafOutput) -> L::LeafOutput {
+        compute()
+    }
+}
+
+/// The [`ComputeHooks`] of [`TinyExprTree::compute_split`], which carry the
+/// leaf context. It is borrowed for `'c`, independently of the tree.
+struct SplitContext<'c, LC: ?Sized>(&'c LC);
+
+impl<LC: ?Sized> Clone for SplitContext<'_, LC> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<LC: ?Sized> Copy for SplitContext<'_, LC> {}
+impl<LC: ?Sized> sealed::Sealed for SplitContext<'_, LC> {}
+impl<BO, L: ComputableLeaf> ComputeHooks<BO, L> for SplitContext<'_, L::LeafContext> {
+    const STATEFUL: bool = false;
+    fn leaf_context(&self) -> Option<&L::LeafContext> {
+        Some(self.0)
+    }
+    fn compute_branch(self, _: usize, compute: impl FnOnce() -> BO) -> BO {
+        compute()
+    }
+    fn compute_leaf(self, _: usize, compute: impl FnOnce() -> L::LeafOutput) -> L::LeafOutput {
+        compute()
+    }
+}
+
+/// Collects the slots of leaves that computed to `true`.
+struct FiredLeaves<LM> {
+    mask: core::cell::Cell<LM>,
+}
+
+impl<LM> sealed::Sealed for &FiredLeaves<LM> {}
+impl<BO, L, LM> ComputeHooks<BO, L> for &FiredLeaves<LM>
+where
+    L: ComputableLeaf<LeafOutput = bool>,
+    LM: Mask,
+{
+    fn compute_branch(self, _: usize, compute: impl FnOnce() -> BO) -> BO {
+        compute()
+    }
+    fn compute_leaf(self, index: usize, compute: impl FnOnce() -> bool) -> bool {
+        let output = compute();
+        if output {
+            self.mask.set(self.mask.get() | (LM::ONE_SELECTED << index));
+        }
+        output
+    }
+}
+
+/// Outputs remembered by [`TinyExprTree::compute_incremental`], stored per
+/// branch and leaf slot in mask tracked arrays with the same mask types as the
+/// tree, so it works without `alloc`. `BO` is the branch output type, `LO` the
+/// leaf output type, and `BOA` and `LOA` the arrays holding them, for example
+/// `MaskTrackedArrayU8<BO>` and `MaskTrackedArrayU16<LO>` for a tree with
+/// `u8` branch and `u16` leaf masks.
+///
+/// A filled slot is a clean output that is reused, and an empty slot is dirty
+/// and computed again. [`TinyExprTree::mark_leaf_dirty`] empties the slots of
+/// a leaf and the branches above it.
+#[derive(Debug)]
+pub struct IncrementalCache<BO, LO, BOA, LOA> {
+    root: Option<BO>,
+    branches: BOA,
+    leaves: LOA,
+    _leaf_output: PhantomData<LO>,
+}
+
+impl<BO, LO, BOA, LOA> IncrementalCache<BO, LO, BOA, LOA>
+where
+    BOA: MaskTrackedArray<BO>,
+    LOA: MaskTrackedArray<LO>,
+{
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            branches: BOA::new(),
+            leaves: LOA::new(),
+            _leaf_output: PhantomData,
+        }
+    }
+    /// Forget every cached output, marking the whole tree dirty.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.branches.clear();
+        self.leaves.clear();
+    }
+}
+
+impl<BO, LO, BOA, LOA> Default for IncrementalCache<BO, LO, BOA, LOA>
+where
+    BOA: MaskTrackedArray<BO>,
+    LOA: MaskTrackedArray<LO>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<BO, LO, BOA, LOA> sealed::Sealed for &IncrementalCache<BO, LO, BOA, LOA> {}
+impl<BO, L, BOA, LOA> ComputeHooks<BO, L> for &IncrementalCache<BO, L::LeafOutput, BOA, LOA>
+where
+    BO: Clone,
+    L: ComputableLeaf<LeafOutput: Clone>,
+    BOA: MaskTrackedArray<BO>,
+    LOA: MaskTrackedArray<L::LeafOutput>,
+{
+    fn compute_branch(self, index: usize, compute: impl FnOnce() -> BO) -> BO {
+        if let Some(output) = self.branches.get_ref(index) {
+            return output.clone();
+        }
+        let output = compute();
+        let _ = self.branches.insert(index, output.clone());
+        output
+    }
+    fn compute_leaf(self, index: usize, compute: impl FnOnce() -> L::LeafOutput) -> L::LeafOutput {
+        if let Some(output) = self.leaves.get_ref(index) {
+            return output.clone();
+        }
+        let output = compute();
+        let _ = self.leaves.insert(index, output.clone());
+        output
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM, H> BranchControls<'a, B, L, BA, LA, BM, LM, H>
 where
-    B: ComputableBranch<L, BA, LA, BM, LM>,
-    L: ComputableLeaf,
     BM: Mask,
     BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
     LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
     LM: Mask,
+    H: Copy,
 {
+    fn new(
+        inner_reference: &'a TreeInner<B, L, BA, LA, BM, LM>,
+        mask: ChildrenMask<BM, LM>,
+        hooks: H,
+    ) -> Self {
+        Self {
+            inner_reference,
+            mask,
+            depth: 0,
+            hooks,
+        }
+    }
+    /// Controls for the sub-branch in slot `index`, which must be filled.
+    ///
+    /// [`ComputableBranch::compute`] takes its controls by value, so every
+    /// sub-branch needs its own. Building one only copies a reference, the
+    /// child's masks, its depth and the hooks, which are `Copy`.
+    fn child(&self, index: usize) -> (&'a BranchNode<B, BM, LM>, Self) {
+        let branch = unsafe { self.inner_reference.branches.get_unchecked_ref(index) };
+        let controls = Self {
+            inner_reference: self.inner_reference,
+            mask: branch.mask,
+            depth: self.depth + 1,
+            hooks: self.hooks,
+        };
+        (branch, controls)
+    }
+    /// How many branches lie between the current branch and the root. The
+    /// root branch is at depth 0 and its sub-branches are at depth 1.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
     /// Mask representing sub-branches
     #[inline]
     pub fn branch_mask(&self) -> BM {
@@ -121,7 +584,118 @@ where
     pub fn has_leaves(&self) -> bool {
         self.mask.leaf_mask != LM::NONE_SELECTED
     }
-    /// Compute the value of all sub-branches specified in the mask.
+    /// Number of sub-branches directly below this branch, direct children
+    /// only. This is the population count of
+    /// [`BranchControls::branch_mask`], so no children are computed to find
+    /// it, and branch logic such as a majority vote can use it up front.
+    #[inline]
+    pub fn filled_branch_count(&self) -> u32 {
+        self.mask.branch_mask.count_ones()
+    }
+    /// Number of leaves directly below this branch, direct children only.
+    /// Like [`BranchControls::filled_branch_count`], this only reads the
+    /// mask.
+    #[inline]
+    pub fn filled_leaf_count(&self) -> u32 {
+        self.mask.leaf_mask.count_ones()
+    }
+    /// Number of sub-branches and leaves directly below this branch.
+    #[inline]
+    pub fn child_count(&self) -> usize {
+        (self.filled_branch_count() + self.filled_leaf_count()) as usize
+    }
+    /// The value of the nth sub-branch, counted in slot order, without
+    /// computing it. Returns `None` if there are not that many sub-branches.
+    pub fn branch_value(&self, index: u32) -> Option<&'a B> {
+        let inner = self.inner_reference;
+        let slot = inner
+            .branches
+            .iter_filled_indices_mask(self.mask.branch_mask)
+            .nth(index as usize)?;
+        inner.branches.get_ref(slot).map(|branch| &branch.branch)
+    }
+    /// The value of the nth leaf, counted in slot order, without computing
+    /// it. Returns `None` if there are not that many leaves.
+    pub fn leaf_value(&self, index: u32) -> Option<&'a L> {
+        let inner = self.inner_reference;
+        let slot = inner
+            .leaves
+            .iter_filled_indices_mask(self.mask.leaf_mask)
+            .nth(index as usize)?;
+        inner.leaves.get_ref(slot).map(|leaf| &leaf.leaf)
+    }
+    /// The value of the sub-branch in slot `slot` of the branch array, such as
+    /// a slot returned by [`BranchControls::compute_first_success`], without
+    /// computing it. Returns `None` if that slot is not a sub-branch of this
+    /// branch.
+    pub fn branch_in_slot(&self, slot: u32) -> Option<&'a B> {
+        if slot >= BM::MAX_SELECTIONS
+            || self.mask.branch_mask & (BM::ONE_SELECTED << slot as usize) == BM::NONE_SELECTED
+        {
+            return None;
+        }
+        let inner = self.inner_reference;
+        inner
+            .branches
+            .get_ref(slot as usize)
+            .map(|branch| &branch.branch)
+    }
+    /// The value of the leaf in slot `slot` of the leaf array, without
+    /// computing it. Returns `None` if that slot is not a leaf of this branch.
+    pub fn leaf_in_slot(&self, slot: u32) -> Option<&'a L> {
+        if slot >= LM::MAX_SELECTIONS
+            || self.mask.leaf_mask & (LM::ONE_SELECTED << slot as usize) == LM::NONE_SELECTED
+        {
+            return None;
+        }
+        let inner = self.inner_reference;
+        inner.leaves.get_ref(slot as usize).map(|leaf| &leaf.leaf)
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM, H> BranchControls<'a, B, L, BA, LA, BM, LM, H>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM>,
+    L: ComputableLeaf,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+    H: ComputeHooks<B::BranchOutput, L>,
+{
+    /// The leaf context passed to [`TinyExprTree::compute_split`], or `None`
+    /// if the tree was computed some other way. Branches whose leaves take a
+    /// different context type than themselves can hand this to
+    /// [`BranchControls::compute_leaves`].
+    #[inline]
+    pub fn leaf_context(&self) -> Option<&L::LeafContext> {
+        self.hooks.leaf_context()
+    }
+    /// Compute the sub-branch in slot `index`, which must be filled.
+    #[inline]
+    fn compute_branch_at(&self, context: &B::BranchContext, index: usize) -> B::BranchOutput {
+        self.hooks.compute_branch(index, || {
+            let (branch, controls) = self.child(index);
+            branch.branch.compute(context, controls)
+        })
+    }
+    /// Compute the leaf in slot `index`, which must be filled.
+    #[inline]
+    fn compute_leaf_at(&self, context: &L::LeafContext, index: usize) -> L::LeafOutput {
+        self.hooks.compute_leaf(index, || {
+            let leaf = unsafe { self.inner_reference.leaves.get_unchecked_ref(index) };
+            leaf.leaf.compute_indexed(context, index as u32)
+        })
+    }
+    /// Compute the value of all sub-branches specified in the mask. `mask` is
+    /// narrowed to this branch's own sub-branches and to filled slots, so
+    /// passing `ALL_SELECTED` never reaches an empty slot.
+    ///
+    /// The returned iterator is lazy: each sub-branch is only computed when
+    /// the iterator is advanced past it, so short-circuiting adapters such as
+    /// [`Iterator::any`] and [`Iterator::all`] never recurse into the
+    /// sub-branches after the one that decided the result. The same holds for
+    /// every other `compute_*` iterator on [`BranchControls`].
     #[inline]
     pub fn compute_branches(
         &self,
@@ -133,15 +707,7 @@ where
             .inner_reference
             .branches
             .iter_filled_indices_mask(mask & branch_control_mask);
-        indices_iter.map(|index| {
-            let branch = unsafe { self.inner_reference.branches.get_unchecked_mut(index) };
-            let mask = branch.mask;
-            let controls = BranchControls {
-                inner_reference: self.inner_reference,
-                mask,
-            };
-            branch.branch.compute(context, controls)
-        })
+        indices_iter.map(|index| self.compute_branch_at(context, index))
     }
     /// Compute the value of all sub-branches
     #[inline]
@@ -161,10 +727,45 @@ where
         self.inner_reference
             .leaves
             .iter_filled_indices_mask(mask & self.mask.leaf_mask)
-            .map(|index| {
-                let leaf = unsafe { self.inner_reference.leaves.get_unchecked_mut(index) };
-                leaf.leaf.compute(context)
-            })
+            .map(|index| self.compute_leaf_at(context, index))
+    }
+    /// Same as [`BranchControls::compute_branches`], but sub-branches are
+    /// computed from the highest slot to the lowest, so the most recently
+    /// added sub-branch comes first.
+    #[inline]
+    pub fn compute_branches_rev(
+        &self,
+        context: &B::BranchContext,
+        mask: BA::MaskType,
+    ) -> impl Iterator<Item = B::BranchOutput> {
+        iter_mask_indices_rev(self.inner_reference.branches.mask() & mask & self.mask.branch_mask)
+            .map(|index| self.compute_branch_at(context, index))
+    }
+    /// Same as [`BranchControls::compute_leaves`], but leaves are computed from
+    /// the highest slot to the lowest, so the most recently added leaf comes
+    /// first.
+    #[inline]
+    pub fn compute_leaves_rev(
+        &self,
+        context: &L::LeafContext,
+        mask: LA::MaskType,
+    ) -> impl Iterator<Item = L::LeafOutput> {
+        iter_mask_indices_rev(self.inner_reference.leaves.mask() & mask & self.mask.leaf_mask)
+            .map(|index| self.compute_leaf_at(context, index))
+    }
+    /// Compute the value of sub-leaves specified in the mask, yielding each
+    /// output with the bit index of the leaf's slot. Slots are assigned in the
+    /// order leaves were added, so this index reflects that ordering.
+    #[inline]
+    pub fn compute_leaves_indexed(
+        &self,
+        context: &L::LeafContext,
+        mask: LA::MaskType,
+    ) -> impl Iterator<Item = (u32, L::LeafOutput)> {
+        self.inner_reference
+            .leaves
+            .iter_filled_indices_mask(mask & self.mask.leaf_mask)
+            .map(|index| (index as u32, self.compute_leaf_at(context, index)))
     }
     /// Compute the values of all leaves
     #[inline]
@@ -176,7 +777,34 @@ where
     }
 }
 
-impl<'a, B, L, BA, LA, BM, LM> BranchControls<'a, B, L, BA, LA, BM, LM>
+impl<'a, B, L, BA, LA, BM, LM, H> BranchControls<'a, B, L, BA, LA, BM, LM, H>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM>,
+    L: ComputableLeaf<LeafContext = B::BranchContext>,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+    H: ComputeHooks<B::BranchOutput, L>,
+{
+    /// Compute the values of all sub-branches and leaves, converting each into
+    /// a common type `O` first. Branches come before leaves, like
+    /// [`BranchControls::compute_all_both`], but leaves and branches do not
+    /// need to share an output type.
+    #[inline]
+    pub fn compute_both_with<O>(
+        &self,
+        context: &B::BranchContext,
+        fb: impl Fn(B::BranchOutput) -> O,
+        fl: impl Fn(L::LeafOutput) -> O,
+    ) -> impl Iterator<Item = O> {
+        self.compute_all_branches(context)
+            .map(fb)
+            .chain(self.compute_all_leaves(context).map(fl))
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM, H> BranchControls<'a, B, L, BA, LA, BM, LM, H>
 where
     B: ComputableBranch<L, BA, LA, BM, LM>,
     L: ComputableLeaf<LeafContext = B::BranchContext, LeafOutput = B::BranchOutput>,
@@ -184,6 +812,7 @@ where
     BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
     LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
     LM: Mask,
+    H: ComputeHooks<B::BranchOutput, L>,
 {
     /// Compute the values of sub-branches and leaves specified in the masks
     #[inline]
@@ -204,6 +833,403 @@ where
     ) -> impl Iterator<Item = B::BranchOutput> {
         self.compute_both(context, BM::ALL_SELECTED, LM::ALL_SELECTED)
     }
+    /// Fold the outputs of all sub-branches and then all leaves into a single
+    /// value, like [`Iterator::fold`] over [`BranchControls::compute_all_both`].
+    #[inline]
+    pub fn fold_children<A>(
+        &self,
+        context: &B::BranchContext,
+        init: A,
+        f: impl FnMut(A, B::BranchOutput) -> A,
+    ) -> A {
+        self.compute_all_both(context).fold(init, f)
+    }
+    /// Compute children in order until one satisfies `is_success`, and return
+    /// its output along with its slot in the branch or leaf array, which can be
+    /// passed to [`BranchControls::branch_in_slot`] or
+    /// [`BranchControls::leaf_in_slot`]. This is not the child's position
+    /// that [`BranchControls::branch_value`] and [`BranchControls::leaf_value`]
+    /// take.
+    /// Sub-branches come before leaves, like in
+    /// [`BranchControls::compute_all_both`]. Children after the successful
+    /// one are never computed. Returns `None` if no child succeeded.
+    #[inline]
+    pub fn compute_first_success(
+        &self,
+        context: &B::BranchContext,
+        is_success: impl Fn(&B::BranchOutput) -> bool,
+    ) -> Option<(ChildSlot, B::BranchOutput)> {
+        let branches = self
+            .inner_reference
+            .branches
+            .iter_filled_indices_mask(self.mask.branch_mask)
+            .map(|index| ChildSlot::Branch(index as u32));
+        let leaves = self
+            .inner_reference
+            .leaves
+            .iter_filled_indices_mask(self.mask.leaf_mask)
+            .map(|index| ChildSlot::Leaf(index as u32));
+        branches.chain(leaves).find_map(|child| {
+            let output = match child {
+                ChildSlot::Branch(index) => self.compute_branch_at(context, index as usize),
+                ChildSlot::Leaf(index) => self.compute_leaf_at(context, index as usize),
+            };
+            is_success(&output).then_some((child, output))
+        })
+    }
+    /// Compute children one at a time, passing each output to `f`, and stop at
+    /// the first [`ControlFlow::Break`]. Sub-branches are visited before
+    /// leaves, like [`BranchControls::compute_both`], and children after the
+    /// break are never computed. Returns the break value, or `None` if every
+    /// child was computed.
+    ///
+    /// [`ControlFlow::Break`]: core::ops::ControlFlow::Break
+    #[inline]
+    pub fn compute_children_while<R>(
+        &self,
+        context: &B::BranchContext,
+        f: impl FnMut(B::BranchOutput) -> core::ops::ControlFlow<R, ()>,
+    ) -> Option<R> {
+        self.compute_all_both(context).try_for_each(f).break_value()
+    }
+    /// Compute all sub-branches and then all leaves, writing the outputs into
+    /// `out` in that order. Stops once `out` is full and returns how many
+    /// outputs were written. A slice of [`BranchControls::child_count`] items
+    /// is always large enough.
+    pub fn compute_children_into(
+        &self,
+        context: &B::BranchContext,
+        out: &mut [B::BranchOutput],
+    ) -> usize {
+        let mut written = 0;
+        for index in self
+            .inner_reference
+            .branches
+            .iter_filled_indices_mask(self.mask.branch_mask)
+        {
+            let Some(slot) = out.get_mut(written) else {
+                return written;
+            };
+            *slot = self.compute_branch_at(context, index);
+            written += 1;
+        }
+        for index in self
+            .inner_reference
+            .leaves
+            .iter_filled_indices_mask(self.mask.leaf_mask)
+        {
+            let Some(slot) = out.get_mut(written) else {
+                return written;
+            };
+            *slot = self.compute_leaf_at(context, index);
+            written += 1;
+        }
+        written
+    }
+    /// Compute all sub-branches and then all leaves into a fixed capacity
+    /// [`heapless::Vec`], for operators that need several passes over the
+    /// outputs without an allocator. If there are more than `N` children, a
+    /// [`heapless::CapacityError`] is returned before any child is computed.
+    /// An `N` equal to the sum of the mask bit widths is always enough.
+    #[cfg(feature = "heapless")]
+    pub fn collect_children<const N: usize>(
+        &self,
+        context: &B::BranchContext,
+    ) -> Result<heapless::Vec<B::BranchOutput, N>, heapless::CapacityError> {
+        if self.child_count() > N {
+            return Err(heapless::CapacityError::default());
+        }
+        let mut outputs = heapless::Vec::new();
+        for output in self.compute_all_both(context) {
+            outputs
+                .push(output)
+                .map_err(|_| heapless::CapacityError::default())?;
+        }
+        Ok(outputs)
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM, H> BranchControls<'a, B, L, BA, LA, BM, LM, H>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM, BranchOutput = bool>,
+    L: ComputableLeaf<LeafContext = B::BranchContext, LeafOutput = bool>,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+    H: ComputeHooks<B::BranchOutput, L>,
+{
+    /// Whether every sub-branch and leaf computes to `true`. Children are
+    /// computed in the order of [`BranchControls::compute_all_both`] and
+    /// computing stops at the first `false`. A branch without children is
+    /// `true`.
+    #[inline]
+    pub fn compute_all(&self, context: &B::BranchContext) -> bool {
+        self.compute_all_both(context).all(core::convert::identity)
+    }
+    /// Whether any sub-branch or leaf computes to `true`. Children are
+    /// computed in the order of [`BranchControls::compute_all_both`] and
+    /// computing stops at the first `true`. A branch without children is
+    /// `false`.
+    #[inline]
+    pub fn compute_any(&self, context: &B::BranchContext) -> bool {
+        self.compute_all_both(context).any(core::convert::identity)
+    }
+    /// Number of sub-branches and leaves that compute to `true`, for
+    /// threshold operators such as "at least `k` of `n`". Every child is
+    /// computed and nothing is collected.
+    #[inline]
+    pub fn count_true(&self, context: &B::BranchContext) -> u32 {
+        self.compute_all_both(context)
+            .filter(|output| *output)
+            .count() as u32
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM> BranchControls<'a, B, L, BA, LA, BM, LM>
+where
+    B: TryComputableBranch<L, BA, LA, BM, LM>,
+    L: TryComputableLeaf,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+{
+    /// Try to compute the value of all sub-branches specified in the mask.
+    #[inline]
+    pub fn try_compute_branches(
+        &self,
+        context: &B::BranchContext,
+        mask: BA::MaskType,
+    ) -> impl Iterator<Item = Result<B::BranchOutput, B::Error>> {
+        self.inner_reference
+            .branches
+            .iter_filled_indices_mask(mask & self.mask.branch_mask)
+            .map(|index| {
+                let (branch, controls) = self.child(index);
+                branch.branch.try_compute(context, controls)
+            })
+    }
+    /// Try to compute the value of all sub-branches
+    #[inline]
+    pub fn try_compute_all_branches(
+        &self,
+        context: &B::BranchContext,
+    ) -> impl Iterator<Item = Result<B::BranchOutput, B::Error>> {
+        self.try_compute_branches(context, BM::ALL_SELECTED)
+    }
+    /// Try to compute the value of sub-leaves specified in the mask
+    #[inline]
+    pub fn try_compute_leaves(
+        &self,
+        context: &L::LeafContext,
+        mask: LA::MaskType,
+    ) -> impl Iterator<Item = Result<L::LeafOutput, L::Error>> {
+        self.inner_reference
+            .leaves
+            .iter_filled_indices_mask(mask & self.mask.leaf_mask)
+            .map(|index| {
+                let leaf = unsafe { self.inner_reference.leaves.get_unchecked_ref(index) };
+                leaf.leaf.try_compute(context)
+            })
+    }
+    /// Try to compute the values of all leaves
+    #[inline]
+    pub fn try_compute_all_leaves(
+        &self,
+        context: &L::LeafContext,
+    ) -> impl Iterator<Item = Result<L::LeafOutput, L::Error>> {
+        self.try_compute_leaves(context, LM::ALL_SELECTED)
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM> BranchControls<'a, B, L, BA, LA, BM, LM>
+where
+    B: TryComputableBranch<L, BA, LA, BM, LM>,
+    L: TryComputableLeaf<
+            LeafContext = B::BranchContext,
+            LeafOutput = B::BranchOutput,
+            Error = B::Error,
+        >,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+{
+    /// Try to compute the values of sub-branches and leaves specified in the
+    /// masks
+    #[inline]
+    pub fn try_compute_both(
+        &self,
+        context: &B::BranchContext,
+        branch_mask: BM,
+        leaf_mask: LM,
+    ) -> impl Iterator<Item = Result<B::BranchOutput, B::Error>> {
+        self.try_compute_branches(context, branch_mask)
+            .chain(self.try_compute_leaves(context, leaf_mask))
+    }
+    /// Try to compute the values of all sub-branches and leaves
+    #[inline]
+    pub fn try_compute_all_both(
+        &self,
+        context: &B::BranchContext,
+    ) -> impl Iterator<Item = Result<B::BranchOutput, B::Error>> {
+        self.try_compute_both(context, BM::ALL_SELECTED, LM::ALL_SELECTED)
+    }
+}
+
+/// A child of a branch, found by its slot in the branch or leaf array.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChildSlot {
+    Branch(u32),
+    Leaf(u32),
+}
+
+impl<'a, B, L, BA, LA, BM, LM> BranchControls<'a, B, L, BA, LA, BM, LM>
+where
+    B: ComputableBranchMut<L, BA, LA, BM, LM>,
+    L: ComputableLeafMut,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+{
+    /// Compute the sub-branch in slot `index`, which must be filled.
+    fn compute_branch_mut_at(
+        &self,
+        context: &mut B::BranchContext,
+        index: usize,
+    ) -> B::BranchOutput {
+        let (branch, controls) = self.child(index);
+        branch.branch.compute_mut(context, controls)
+    }
+    /// Compute the leaf in slot `index`, which must be filled.
+    fn compute_leaf_mut_at(&self, context: &mut L::LeafContext, index: usize) -> L::LeafOutput {
+        let leaf = unsafe { self.inner_reference.leaves.get_unchecked_ref(index) };
+        leaf.leaf.compute_mut(context)
+    }
+    /// Compute the value of all sub-branches specified in the mask. The
+    /// context is borrowed until the iterator is dropped, and each sub-branch
+    /// sees the changes made by the ones before it.
+    #[inline]
+    pub fn compute_branches_mut(
+        &self,
+        context: &mut B::BranchContext,
+        mask: BA::MaskType,
+    ) -> impl Iterator<Item = B::BranchOutput> {
+        self.inner_reference
+            .branches
+            .iter_filled_indices_mask(mask & self.mask.branch_mask)
+            .map(move |index| self.compute_branch_mut_at(context, index))
+    }
+    /// Compute the value of all sub-branches
+    #[inline]
+    pub fn compute_all_branches_mut(
+        &self,
+        context: &mut B::BranchContext,
+    ) -> impl Iterator<Item = B::BranchOutput> {
+        self.compute_branches_mut(context, BM::ALL_SELECTED)
+    }
+    /// Compute the value of sub-leaves specified in the mask, in slot order.
+    #[inline]
+    pub fn compute_leaves_mut(
+        &self,
+        context: &mut L::LeafContext,
+        mask: LA::MaskType,
+    ) -> impl Iterator<Item = L::LeafOutput> {
+        self.inner_reference
+            .leaves
+            .iter_filled_indices_mask(mask & self.mask.leaf_mask)
+            .map(move |index| self.compute_leaf_mut_at(context, index))
+    }
+    /// Compute the values of all leaves
+    #[inline]
+    pub fn compute_all_leaves_mut(
+        &self,
+        context: &mut L::LeafContext,
+    ) -> impl Iterator<Item = L::LeafOutput> {
+        self.compute_leaves_mut(context, LM::ALL_SELECTED)
+    }
+}
+
+impl<'a, B, L, BA, LA, BM, LM> BranchControls<'a, B, L, BA, LA, BM, LM>
+where
+    B: ComputableBranchMut<L, BA, LA, BM, LM>,
+    L: ComputableLeafMut<LeafContext = B::BranchContext, LeafOutput = B::BranchOutput>,
+    BM: Mask,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask,
+{
+    /// Compute the values of sub-branches and then leaves specified in the
+    /// masks, handing the context on from one child to the next.
+    #[inline]
+    pub fn compute_both_mut(
+        &self,
+        context: &mut B::BranchContext,
+        branch_mask: BM,
+        leaf_mask: LM,
+    ) -> impl Iterator<Item = B::BranchOutput> {
+        let branches = self
+            .inner_reference
+            .branches
+            .iter_filled_indices_mask(branch_mask & self.mask.branch_mask)
+            .map(|index| ChildSlot::Branch(index as u32));
+        let leaves = self
+            .inner_reference
+            .leaves
+            .iter_filled_indices_mask(leaf_mask & self.mask.leaf_mask)
+            .map(|index| ChildSlot::Leaf(index as u32));
+        branches.chain(leaves).map(move |child| match child {
+            ChildSlot::Branch(index) => self.compute_branch_mut_at(context, index as usize),
+            ChildSlot::Leaf(index) => self.compute_leaf_mut_at(context, index as usize),
+        })
+    }
+    /// Compute the values of all sub-branches and leaves
+    #[inline]
+    pub fn compute_all_both_mut(
+        &self,
+        context: &mut B::BranchContext,
+    ) -> impl Iterator<Item = B::BranchOutput> {
+        self.compute_both_mut(context, BM::ALL_SELECTED, LM::ALL_SELECTED)
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: ComputableBranchMut<L, BA, LA, BM, LM>,
+    L: ComputableLeafMut,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// Version of [`TinyExprTree::compute`] for trees made of
+    /// [`ComputableBranchMut`]es and [`ComputableLeafMut`]s, which may change
+    /// the context as they compute.
+    pub fn compute_mut(&self, context: &mut B::BranchContext) -> B::BranchOutput {
+        let base_access = BranchControls::new(&self.inner, self.root.mask, NoHooks);
+        self.root.branch.compute_mut(context, base_access)
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: TryComputableBranch<L, BA, LA, BM, LM>,
+    L: TryComputableLeaf,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// Fallible version of [`TinyExprTree::compute`] for trees made of
+    /// [`TryComputableBranch`]es and [`TryComputableLeaf`]s.
+    pub fn try_compute(&self, context: &B::BranchContext) -> Result<B::BranchOutput, B::Error> {
+        let base_access = BranchControls::new(&self.inner, self.root.mask, NoHooks);
+        self.root.branch.try_compute(context, base_access)
+    }
 }
 
 impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
@@ -219,12 +1245,497 @@ where
     /// exact operations done is dependent on the [`ComputableBranch`] and
     /// [`ComputableLeaf`] implementations you supply.
     pub fn compute(&self, context: &B::BranchContext) -> B::BranchOutput {
-        let base_access = BranchControls {
-            inner_reference: &self.inner,
-            mask: self.root.mask,
-        };
+        let base_access = BranchControls::new(&self.inner, self.root.mask, NoHooks);
         self.root.branch.compute(context, base_access)
     }
+    /// Compute the tree once for every context in `contexts`, in order. The
+    /// returned iterator is lazy, so nothing is computed until it is advanced.
+    pub fn compute_batch<'c, I>(&self, contexts: I) -> impl Iterator<Item = B::BranchOutput>
+    where
+        I: IntoIterator<Item = &'c B::BranchContext>,
+        B::BranchContext: 'c,
+    {
+        contexts.into_iter().map(|context| self.compute(context))
+    }
+    /// Same as [`TinyExprTree::compute`], but takes a small [`Copy`] context
+    /// such as a `bool` by value, so call sites can write
+    /// `tree.compute_copy(true)`.
+    #[inline]
+    pub fn compute_copy(&self, context: B::BranchContext) -> B::BranchOutput
+    where
+        B::BranchContext: Copy,
+    {
+        self.compute(&context)
+    }
+    /// Like [`TinyExprTree::compute`], but also passes a separate context for
+    /// the leaves. Every branch can read it through
+    /// [`BranchControls::leaf_context`], so leaves do not have to accept the
+    /// branch context type.
+    pub fn compute_split(
+        &self,
+        branch_context: &B::BranchContext,
+        leaf_context: &L::LeafContext,
+    ) -> B::BranchOutput {
+        let base_access =
+            BranchControls::new(&self.inner, self.root.mask, SplitContext(leaf_context));
+        self.root.branch.compute(branch_context, base_access)
+    }
+    /// Same as [`TinyExprTree::compute`], but also returns a mask with the
+    /// slot bit of every leaf that computed to `true`, numbered like the
+    /// slots [`alloc_gen::ConstructableTreeBranch::to_tree`] assigns. Leaves
+    /// that were never computed are not set. This does not allocate.
+    pub fn compute_with_fired_mask(&self, context: &B::BranchContext) -> (B::BranchOutput, LM)
+    where
+        L: ComputableLeaf<LeafOutput = bool>,
+    {
+        let fired = FiredLeaves {
+            mask: core::cell::Cell::new(LM::NONE_SELECTED),
+        };
+        let base_access = BranchControls::new(&self.inner, self.root.mask, &fired);
+        let output = self.root.branch.compute(context, base_access);
+        (output, fired.mask.get())
+    }
+    /// Same as [`TinyExprTree::compute`], but fails with [`BudgetExceeded`]
+    /// without computing anything when a full pass over the tree would
+    /// compute more than `max_nodes` branches and leaves, counting the root.
+    ///
+    /// In a full pass every branch computes each of its children once, so for
+    /// trees whose branches compute each child at most once, `max_nodes`
+    /// bounds the work of one call, even where short-circuiting would have
+    /// stayed within it. Nothing is counted while computing, since a running
+    /// branch cannot be stopped without handing it fewer children than its
+    /// mask promises, so a branch that computes the same child more than once
+    /// is not bounded.
+    ///
+    /// The check walks at most `max_nodes` nodes on every call. A tree that is
+    /// computed many times with the same budget can compare
+    /// [`TinyExprTree::full_pass_node_count`] against it once and then use
+    /// [`TinyExprTree::compute`].
+    pub fn compute_budgeted(
+        &self,
+        context: &B::BranchContext,
+        max_nodes: u32,
+    ) -> Result<B::BranchOutput, BudgetExceeded> {
+        if self.full_pass_nodes(&self.root, max_nodes).is_none() {
+            return Err(BudgetExceeded);
+        }
+        Ok(self.compute(context))
+    }
+    /// Same as [`TinyExprTree::compute`], but outputs still clean in `cache`
+    /// are reused instead of being computed again, and everything computed is
+    /// added to it. Call [`TinyExprTree::mark_leaf_dirty`] for every leaf
+    /// whose output may have changed since the last call, so only the
+    /// branches on a path from those leaves to the root are computed again.
+    /// The cache does not know about `context`, so a context change that
+    /// affects every leaf needs [`IncrementalCache::clear`]. This does not
+    /// allocate.
+    pub fn compute_incremental<BOA, LOA>(
+        &self,
+        context: &B::BranchContext,
+        cache: &mut IncrementalCache<B::BranchOutput, L::LeafOutput, BOA, LOA>,
+    ) -> B::BranchOutput
+    where
+        B::BranchOutput: Clone,
+        L::LeafOutput: Clone,
+        BOA: MaskTrackedArray<B::BranchOutput, MaskType = BM>,
+        LOA: MaskTrackedArray<L::LeafOutput, MaskType = LM>,
+    {
+        if let Some(root) = &cache.root {
+            return root.clone();
+        }
+        let base_access = BranchControls::new(&self.inner, self.root.mask, &*cache);
+        let output = self.root.branch.compute(context, base_access);
+        cache.root = Some(output.clone());
+        output
+    }
+    /// Mark the leaf in slot `index` dirty in `cache`, together with every
+    /// branch on a path from it up to the root, so the next
+    /// [`TinyExprTree::compute_incremental`] computes them again and reuses
+    /// everything else. Trees made by
+    /// [`alloc_gen::ConstructableTreeBranch::to_tree_dedup`] may share the
+    /// leaf between several parents, and all of them are marked.
+    ///
+    /// Returns `false` and marks nothing if slot `index` holds no leaf,
+    /// including when it is past the end of the leaf array.
+    pub fn mark_leaf_dirty<BOA, LOA>(
+        &self,
+        cache: &mut IncrementalCache<B::BranchOutput, L::LeafOutput, BOA, LOA>,
+        index: u32,
+    ) -> bool
+    where
+        BOA: MaskTrackedArray<B::BranchOutput, MaskType = BM>,
+        LOA: MaskTrackedArray<L::LeafOutput, MaskType = LM>,
+    {
+        if !self.inner.leaves.contains_item_at(index as usize) {
+            return false;
+        }
+        cache.leaves.remove(index as usize);
+        let leaf_bit = LM::ONE_SELECTED << index as usize;
+        // Grow the set of dirty branches until no other branch points into it.
+        let mut dirty = BM::NONE_SELECTED;
+        loop {
+            let parents = self
+                .inner
+                .branches
+                .iter_filled_indices()
+                .filter(|&slot| {
+                    let mask = unsafe { self.inner.branches.get_unchecked_ref(slot) }.mask;
+                    mask.leaf_mask & leaf_bit != LM::NONE_SELECTED
+                        || mask.branch_mask & dirty != BM::NONE_SELECTED
+                })
+                .fold(BM::NONE_SELECTED, |parents, slot| {
+                    parents | (BM::ONE_SELECTED << slot)
+                });
+            if parents == dirty {
+                break;
+            }
+            dirty = parents;
+        }
+        for slot in dirty.mask_to_indices() {
+            cache.branches.remove(slot);
+        }
+        cache.root = None;
+        true
+    }
+    /// Number of branches and leaves, counting the root, that a full pass
+    /// over the tree computes if every branch computes each of its children
+    /// once. A node shared by [`alloc_gen::ConstructableTreeBranch::to_tree_dedup`]
+    /// counts once per parent. Saturates at `u32::MAX`.
+    pub fn full_pass_node_count(&self) -> u32 {
+        self.full_pass_nodes(&self.root, u32::MAX)
+            .unwrap_or(u32::MAX)
+    }
+    /// Number of nodes computing `node` visits if every branch computes each
+    /// of its children once, counting `node` itself, or `None` if that is
+    /// more than `limit`. Stops counting as soon as the limit is passed.
+    fn full_pass_nodes(&self, node: &BranchNode<B, BM, LM>, limit: u32) -> Option<u32> {
+        let mut nodes = 1 + node.mask.leaf_mask.count_ones();
+        if nodes > limit {
+            return None;
+        }
+        for branch in self.inner.branches.iter_mask(node.mask.branch_mask) {
+            nodes += self.full_pass_nodes(branch, limit - nodes)?;
+        }
+        Some(nodes)
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM>,
+    L: ComputableLeaf<LeafContext = B::BranchContext, LeafOutput = B::BranchOutput>,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// Compute the root branch's sub-branches and then its leaves, without
+    /// running the root branch itself. This yields the same outputs as
+    /// [`BranchControls::compute_all_both`] would inside the root, so they can
+    /// be combined in application code instead of by a branch operator. Like
+    /// the other `compute_*` iterators, children are computed lazily.
+    pub fn compute_children(
+        &self,
+        context: &B::BranchContext,
+    ) -> impl Iterator<Item = B::BranchOutput> {
+        let access = BranchControls::new(&self.inner, self.root.mask, NoHooks);
+        let branches = self
+            .inner
+            .branches
+            .iter_filled_indices_mask(self.root.mask.branch_mask)
+            .map(move |index| access.compute_branch_at(context, index));
+        let leaves = self
+            .inner
+            .leaves
+            .iter_filled_indices_mask(self.root.mask.leaf_mask)
+            .map(move |index| access.compute_leaf_at(context, index));
+        branches.chain(leaves)
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    /// Number of branches in the tree, including the root branch.
+    pub fn branch_count(&self) -> usize {
+        self.inner.branches.len() as usize + 1
+    }
+    /// Number of leaves in the tree.
+    pub fn leaf_count(&self) -> usize {
+        self.inner.leaves.len() as usize
+    }
+    /// Number of branches and leaves in the tree, including the root branch.
+    pub fn node_count(&self) -> usize {
+        self.branch_count() + self.leaf_count()
+    }
+    /// Iterate over every leaf value stored in the tree in slot order. This
+    /// does not allocate and can be used on-device for diagnostics.
+    pub fn iter_leaves(&self) -> impl Iterator<Item = &L> {
+        self.inner.leaves.iter().map(|leaf| &leaf.leaf)
+    }
+    /// Mutable access to the leaf in slot `index` of the leaf array, which is
+    /// bit `index` of the leaf masks. Returns `None` if the slot is empty.
+    pub fn leaf_mut(&mut self, index: u32) -> Option<&mut L> {
+        self.inner
+            .leaves
+            .get_mut(index as usize)
+            .map(|leaf| &mut leaf.leaf)
+    }
+    /// Mutable access to the branch in slot `index` of the branch array, which
+    /// is bit `index` of the branch masks. The root branch is not stored in
+    /// the array; use [`TinyExprTree::root_mut`] for it. Returns `None` if the
+    /// slot is empty.
+    pub fn branch_mut(&mut self, index: u32) -> Option<&mut B> {
+        self.inner
+            .branches
+            .get_mut(index as usize)
+            .map(|branch| &mut branch.branch)
+    }
+    /// The root branch value.
+    pub fn root(&self) -> &B {
+        &self.root.branch
+    }
+    /// Mutable access to the root branch.
+    pub fn root_mut(&mut self) -> &mut B {
+        &mut self.root.branch
+    }
+    /// Iterate over every branch value stored in the tree. The root branch is
+    /// not kept in the branch array, so it is always yielded first, followed
+    /// by the other branches in slot order. This does not allocate.
+    pub fn iter_branches(&self) -> impl Iterator<Item = &B> {
+        core::iter::once(&self.root.branch)
+            .chain(self.inner.branches.iter().map(|branch| &branch.branch))
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> TinyExprTree<B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    /// The mask selecting the root branch's sub-branches and leaves.
+    pub fn root_mask(&self) -> ChildrenMask<BM, LM> {
+        self.root.mask
+    }
+    /// Whether the root branch has exactly one leaf and no sub-branches, so the
+    /// whole tree is a single leaf under the root. This only reads the root's
+    /// mask. Such a tree can be computed by computing that leaf directly,
+    /// if the root operator passes a single child through unchanged.
+    pub fn is_trivial(&self) -> bool {
+        self.root.mask.branch_mask == BM::NONE_SELECTED
+            && self.root.mask.leaf_mask.count_ones() == 1
+    }
+    /// Whether every branch slot is filled, so no more sub-branches fit. The
+    /// root branch is stored outside the array and is not counted.
+    pub fn branches_full(&self) -> bool {
+        self.inner.branches.mask().count_ones() == BM::ALL_SELECTED.count_ones()
+    }
+    /// Whether every leaf slot is filled, so no more leaves fit.
+    pub fn leaves_full(&self) -> bool {
+        self.inner.leaves.mask().count_ones() == LM::ALL_SELECTED.count_ones()
+    }
+    /// Look up a branch by its path from the root. Each element picks the
+    /// nth sub-branch, counted in slot order, of the branch reached so far,
+    /// so `[0, 2]` is the third sub-branch of the root's first sub-branch. An
+    /// empty path is the root. Returns `None` if any index is past the number
+    /// of sub-branches at that step.
+    pub fn branch_at_path(&self, path: &[u32]) -> Option<&B> {
+        self.branch_node_at_path(path).map(|node| &node.branch)
+    }
+    /// Look up a leaf by its path from the root. All elements but the last
+    /// walk sub-branches like [`TinyExprTree::branch_at_path`], and the last
+    /// picks the nth leaf of the branch reached. Returns `None` if the path
+    /// is empty or any index is out of range.
+    pub fn leaf_at_path(&self, path: &[u32]) -> Option<&L> {
+        let (&leaf, branches) = path.split_last()?;
+        let node = self.branch_node_at_path(branches)?;
+        let index = self
+            .inner
+            .leaves
+            .iter_filled_indices_mask(node.mask.leaf_mask)
+            .nth(leaf as usize)?;
+        self.inner.leaves.get_ref(index).map(|leaf| &leaf.leaf)
+    }
+    fn branch_node_at_path(&self, path: &[u32]) -> Option<&BranchNode<B, BM, LM>> {
+        path.iter().try_fold(&self.root, |node, &child| {
+            let index = self
+                .inner
+                .branches
+                .iter_filled_indices_mask(node.mask.branch_mask)
+                .nth(child as usize)?;
+            self.inner.branches.get_ref(index)
+        })
+    }
+    /// Assemble a tree from arrays filled by hand, for example from flash on a
+    /// target without an allocator. `root` is stored outside the branch array,
+    /// and each branch selects its children by slot through its mask.
+    ///
+    /// The assembled tree is checked with [`validate`](Self::validate) before
+    /// it is returned. Several branches may share a child, like trees made by
+    /// [`alloc_gen::ConstructableTreeBranch::to_tree_dedup`].
+    pub fn from_parts(
+        root: BranchNode<B, BM, LM>,
+        branches: BA,
+        leaves: LA,
+    ) -> Result<Self, ValidationError> {
+        let tree = Self {
+            root,
+            inner: TreeInner {
+                branches,
+                leaves,
+                _phantom: PhantomData,
+            },
+        };
+        tree.validate()?;
+        Ok(tree)
+    }
+    /// Check that every mask in the tree, including the root's, only selects
+    /// filled slots, and that no branch is its own descendant. Computing a
+    /// tree that fails this reads empty slots or never finishes, so trees
+    /// loaded from untrusted storage, for example through `serde`, should be
+    /// validated first. Returns the first problem found, checking the root and
+    /// then the other branches in slot order.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let root = core::iter::once((None, &self.root));
+        let branches = self
+            .inner
+            .branches
+            .iter_filled_indices()
+            .map(|index| Some(index as u32))
+            .zip(self.inner.branches.iter());
+        for (branch, node) in root.chain(branches) {
+            let missing = node.mask.branch_mask & !self.inner.branches.mask();
+            if missing != BM::NONE_SELECTED {
+                return Err(ValidationError::MissingBranch {
+                    branch,
+                    slot: missing.trailing_zeros(),
+                });
+            }
+            let missing = node.mask.leaf_mask & !self.inner.leaves.mask();
+            if missing != LM::NONE_SELECTED {
+                return Err(ValidationError::MissingLeaf {
+                    branch,
+                    slot: missing.trailing_zeros(),
+                });
+            }
+        }
+        if has_cycle(&self.inner.branches) {
+            return Err(ValidationError::Cycle);
+        }
+        Ok(())
+    }
+    /// Walk the tree depth-first, calling `visitor` on each node. A branch is
+    /// visited before its sub-branches, which are visited before its leaves,
+    /// and children are visited in slot order. This does not allocate.
+    pub fn accept<V: Visitor<B, L, BM, LM>>(&self, visitor: &mut V) {
+        self.accept_branch(&self.root, visitor);
+    }
+    fn accept_branch<V: Visitor<B, L, BM, LM>>(
+        &self,
+        node: &BranchNode<B, BM, LM>,
+        visitor: &mut V,
+    ) {
+        visitor.visit_branch(&node.branch, node.mask);
+        for branch in self.inner.branches.iter_mask(node.mask.branch_mask) {
+            self.accept_branch(branch, visitor);
+        }
+        for leaf in self.inner.leaves.iter_mask(node.mask.leaf_mask) {
+            visitor.visit_leaf(&leaf.leaf);
+        }
+    }
+}
+
+/// Whether some branch in `branches` is its own descendant.
+fn has_cycle<B, BA, BM, LM>(branches: &BA) -> bool
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    BM: Mask,
+{
+    // Mark branches whose sub-branches are all marked until nothing changes.
+    // Anything left unmarked is part of, or above, a cycle.
+    let mut acyclic = BM::NONE_SELECTED;
+    loop {
+        let marked = branches
+            .iter_filled_indices()
+            .zip(branches.iter())
+            .filter(|(_, node)| node.mask.branch_mask & !acyclic == BM::NONE_SELECTED)
+            .fold(BM::NONE_SELECTED, |marked, (index, _)| {
+                marked | (BM::ONE_SELECTED << index)
+            });
+        if marked == acyclic {
+            return acyclic != branches.mask();
+        }
+        acyclic = marked;
+    }
+}
+
+/// Returned by [`TinyExprTree::compute_budgeted`] when a full pass over the
+/// tree needs more nodes than the budget allows.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExceeded;
+
+impl core::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "node budget exceeded")
+    }
+}
+
+impl core::error::Error for BudgetExceeded {}
+
+/// The first problem found by [`TinyExprTree::validate`] or
+/// [`TinyExprTree::from_parts`]. `branch` is the slot
+/// of the branch whose mask is wrong, or `None` for the root branch.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A branch mask selects the empty branch slot `slot`.
+    MissingBranch { branch: Option<u32>, slot: u32 },
+    /// A leaf mask selects the empty leaf slot `slot`.
+    MissingLeaf { branch: Option<u32>, slot: u32 },
+    /// A branch is one of its own descendants.
+    Cycle,
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let owner = |f: &mut core::fmt::Formatter<'_>, branch: &Option<u32>| match branch {
+            Some(branch) => write!(f, "branch {branch}"),
+            None => write!(f, "the root branch"),
+        };
+        match self {
+            Self::MissingBranch { branch, slot } => {
+                owner(f, branch)?;
+                write!(f, " selects empty branch slot {slot}")
+            }
+            Self::MissingLeaf { branch, slot } => {
+                owner(f, branch)?;
+                write!(f, " selects empty leaf slot {slot}")
+            }
+            Self::Cycle => write!(f, "a branch is its own descendant"),
+        }
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+/// Analysis run over a finished tree by [`TinyExprTree::accept`]. Both methods
+/// do nothing by default, so a visitor only has to implement the nodes it
+/// cares about.
+pub trait Visitor<B, L, BM, LM> {
+    /// Called for every branch, including the root. `mask` selects its
+    /// sub-branches and leaves.
+    fn visit_branch(&mut self, branch: &B, mask: ChildrenMask<BM, LM>) {
+        let _ = (branch, mask);
+    }
+    /// Called for every leaf.
+    fn visit_leaf(&mut self, leaf: &L) {
+        let _ = leaf;
+    }
 }
 
 /// Makes type aliases for [`TinyExprTree`] to make naming them easier especially
@@ -235,43 +1746,76 @@ where
 /// 4. The mask type for branch nodes.
 /// 5. The mask type for leaf nodes.
 ///
+/// The identifier may be preceded by a visibility such as `pub`, which is
+/// applied to every generated alias. Besides the tree alias itself, the
+/// branch and leaf array types are aliased as the identifier suffixed with
+/// `BA` and `LA`, so `make_tree_aliases!(MiniTree, ...)` also defines
+/// `MiniTreeBA` and `MiniTreeLA`. The macro can therefore be used several
+/// times in one module.
+///
 /// The capacity of the tree for branch and leaf nodes is equal to the number
 /// of bits in the branch and leaf node masks.
+/// Masks can be `u8`, `u16`, `u32`, `u64` or `u128`. `usize` is rejected with
+/// a compile error because `mask-tracked-array` does not provide a `usize`
+/// backed array; that would have to be added upstream first.
+///
+/// ```compile_fail
+/// # use tiny_expr_tree::make_tree_aliases;
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanComparator { And, Or }
+/// # #[derive(Debug, Clone, PartialEq)]
+/// # enum BooleanLeaf { True, False, InsertedValue }
+/// make_tree_aliases!(WideTree, BooleanComparator, BooleanLeaf, usize, u8);
+/// ```
+///
+/// This puts a ceiling of 128 sub-branches and 128 leaves on a tree. Wider
+/// array backed masks such as `[u64; N]` are not possible yet, since the
+/// [`Mask`] trait of `mask-tracked-array` requires a primitive integer.
+/// [`alloc_gen::ConstructableTreeBranch::to_tree_dedup`] can help a tree that
+/// repeats itself fit.
 #[macro_export]
 macro_rules! make_tree_aliases {
     (@BA_GENERATION $vis:vis $alias_name:ident, $branch_node:ty, $leaf_node:ty, $lm:ty, u8) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU8<BranchNode<$branch_node, u8, $lm>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU8<$crate::BranchNode<$branch_node, u8, $lm>>;
     };
     (@BA_GENERATION $vis:vis $alias_name:ident, $branch_node:ty, $leaf_node:ty, $lm:ty, u16) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU16<BranchNode<$branch_node, u16, $lm>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU16<$crate::BranchNode<$branch_node, u16, $lm>>;
     };
     (@BA_GENERATION $vis:vis $alias_name:ident, $branch_node:ty, $leaf_node:ty, $lm:ty, u32) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU32<BranchNode<$branch_node, u32, $lm>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU32<$crate::BranchNode<$branch_node, u32, $lm>>;
     };
     (@BA_GENERATION $vis:vis $alias_name:ident, $branch_node:ty, $leaf_node:ty, $lm:ty, u64) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU64<BranchNode<$branch_node, u64, $lm>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU64<$crate::BranchNode<$branch_node, u64, $lm>>;
     };
     (@BA_GENERATION $vis:vis $alias_name:ident, $branch_node:ty, $leaf_node:ty, $lm:ty, u128) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU128<BranchNode<$branch_node, u128, $lm>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU128<$crate::BranchNode<$branch_node, u128, $lm>>;
+    };
+    (@BA_GENERATION $vis:vis $alias_name:ident, $branch_node:ty, $leaf_node:ty, $lm:ty, usize) => {
+        compile_error!("usize masks are not supported by mask-tracked-array; use u32 or u64 instead");
     };
     (@LA_GENERATION $vis:vis $alias_name:ident, $leaf_node:ty, u8) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU8<LeafNode<$leaf_node>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU8<$crate::LeafNode<$leaf_node>>;
     };
     (@LA_GENERATION $vis:vis $alias_name:ident, $leaf_node:ty, u16) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU16<LeafNode<$leaf_node>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU16<$crate::LeafNode<$leaf_node>>;
     };
     (@LA_GENERATION $vis:vis $alias_name:ident, $leaf_node:ty, u32) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU32<LeafNode<$leaf_node>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU32<$crate::LeafNode<$leaf_node>>;
     };
     (@LA_GENERATION $vis:vis $alias_name:ident, $leaf_node:ty, u64) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU64<LeafNode<$leaf_node>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU64<$crate::LeafNode<$leaf_node>>;
     };
     (@LA_GENERATION $vis:vis $alias_name:ident, $leaf_node:ty, u128) => {
-        $vis type $alias_name = mask_tracked_array::MaskTrackedArrayU128<LeafNode<$leaf_node>>;
+        $vis type $alias_name = $crate::MaskTrackedArrayU128<$crate::LeafNode<$leaf_node>>;
+    };
+    (@LA_GENERATION $vis:vis $alias_name:ident, $leaf_node:ty, usize) => {
+        compile_error!("usize masks are not supported by mask-tracked-array; use u32 or u64 instead");
     };
     ($vis:vis $tree_ident:ident, $branch_node:ty, $leaf_node:ty, $bm:tt, $lm:tt) => {
-        make_tree_aliases!(@BA_GENERATION $vis BA, $branch_node, $leaf_node, $lm, $bm);
-        make_tree_aliases!(@LA_GENERATION $vis LA, $leaf_node, $lm);
-        $vis type $tree_ident = TinyExprTree<$branch_node, $leaf_node, BA, LA, $bm, $lm>;
+        $crate::__paste::paste! {
+            $crate::make_tree_aliases!(@BA_GENERATION $vis [<$tree_ident BA>], $branch_node, $leaf_node, $lm, $bm);
+            $crate::make_tree_aliases!(@LA_GENERATION $vis [<$tree_ident LA>], $leaf_node, $lm);
+            $vis type $tree_ident = $crate::TinyExprTree<$branch_node, $leaf_node, [<$tree_ident BA>], [<$tree_ident LA>], $bm, $lm>;
+        }
     };
 }
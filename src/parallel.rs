@@ -0,0 +1,137 @@
+//! Parallel computation of sub-branches using `rayon`. This module requires
+//! the `rayon` feature flag and is meant for host-side simulation.
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use rayon::iter::{Either, IntoParallelIterator, ParallelIterator};
+
+use mask_tracked_array::{Mask, MaskTrackedArray};
+
+use crate::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, LeafNode, NoHooks,
+    SplitContext, TreeInner,
+};
+
+/// Shares a [`TreeInner`] between threads. The arrays inside use `Cell`s and
+/// are not `Sync`, but nothing writes to them while the tree is borrowed
+/// immutably for computing, so concurrent reads are fine as long as the
+/// element types are `Sync`.
+struct SharedInner<'a, B, L, BA, LA, BM, LM>(&'a TreeInner<B, L, BA, LA, BM, LM>)
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>;
+
+impl<'a, B, L, BA, LA, BM, LM> SharedInner<'a, B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    /// Taking `self` makes closures capture the whole wrapper rather than
+    /// just the non-`Sync` reference inside it.
+    fn get(self) -> &'a TreeInner<B, L, BA, LA, BM, LM> {
+        self.0
+    }
+}
+
+impl<B, L, BA, LA, BM, LM> Clone for SharedInner<'_, B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<B, L, BA, LA, BM, LM> Copy for SharedInner<'_, B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+}
+unsafe impl<B: Sync, L: Sync, BA, LA, BM: Sync, LM: Sync> Sync
+    for SharedInner<'_, B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+}
+unsafe impl<B: Sync, L: Sync, BA, LA, BM: Sync, LM: Sync> Send
+    for SharedInner<'_, B, L, BA, LA, BM, LM>
+where
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+{
+}
+
+impl<'a, B, L, BA, LA, BM, LM, H> BranchControls<'a, B, L, BA, LA, BM, LM, H>
+where
+    B: ComputableBranch<L, BA, LA, BM, LM> + Sync,
+    B::BranchContext: Sync,
+    B::BranchOutput: Send,
+    L: ComputableLeaf + Sync,
+    L::LeafContext: Sync,
+    BM: Mask + Send + Sync,
+    BA: MaskTrackedArray<BranchNode<B, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<L>, MaskType = LM>,
+    LM: Mask + Sync,
+    H: ComputeHooks<B::BranchOutput, L>,
+{
+    /// Parallel version of [`BranchControls::compute_branches`]. Collecting
+    /// the result keeps the same order as the sequential version.
+    ///
+    /// The hooks of [`TinyExprTree::compute_with_fired_mask`] and the other
+    /// instrumented computes record every child on the calling thread, so
+    /// under those the sub-branches are computed sequentially before being
+    /// handed out, and the hooks see them exactly as with
+    /// [`BranchControls::compute_branches`].
+    /// Only [`TinyExprTree::compute`] and [`TinyExprTree::compute_split`]
+    /// compute in parallel.
+    ///
+    /// [`TinyExprTree::compute`]: crate::TinyExprTree::compute
+    /// [`TinyExprTree::compute_split`]: crate::TinyExprTree::compute_split
+    /// [`TinyExprTree::compute_with_fired_mask`]: crate::TinyExprTree::compute_with_fired_mask
+    pub fn par_compute_branches(
+        &self,
+        context: &B::BranchContext,
+        mask: BA::MaskType,
+    ) -> impl ParallelIterator<Item = B::BranchOutput> {
+        if H::STATEFUL {
+            let outputs = self.compute_branches(context, mask).collect::<Vec<_>>();
+            return Either::Left(outputs.into_par_iter());
+        }
+        let shared = SharedInner(self.inner_reference);
+        let selected = self.inner_reference.branches.mask() & mask & self.mask.branch_mask;
+        let depth = self.depth + 1;
+        let leaf_context = self.leaf_context();
+        let outputs = (0..BM::MAX_SELECTIONS as usize)
+            .into_par_iter()
+            .filter(move |index| selected & (BM::ONE_SELECTED << *index) != BM::NONE_SELECTED)
+            .map(move |index| {
+                let inner_reference = shared.get();
+                let branch = unsafe { inner_reference.branches.get_unchecked_ref(index) };
+                match leaf_context {
+                    Some(leaf_context) => {
+                        let hooks = SplitContext(leaf_context);
+                        let mut controls = BranchControls::new(inner_reference, branch.mask, hooks);
+                        controls.depth = depth;
+                        branch.branch.compute(context, controls)
+                    }
+                    None => {
+                        let mut controls =
+                            BranchControls::new(inner_reference, branch.mask, NoHooks);
+                        controls.depth = depth;
+                        branch.branch.compute(context, controls)
+                    }
+                }
+            });
+        Either::Right(outputs)
+    }
+    /// Parallel version of [`BranchControls::compute_all_branches`].
+    pub fn par_compute_all_branches(
+        &self,
+        context: &B::BranchContext,
+    ) -> impl ParallelIterator<Item = B::BranchOutput> {
+        self.par_compute_branches(context, BM::ALL_SELECTED)
+    }
+}
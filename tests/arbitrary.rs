@@ -0,0 +1,103 @@
+use arbitrary::{Arbitrary, Unstructured};
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, LeafNode,
+    alloc_gen::{ARBITRARY_MAX_DEPTH, ConstructableTreeBranch},
+    make_tree_aliases,
+};
+#[derive(Debug, Clone, PartialEq, Arbitrary)]
+struct Value(u8);
+impl ComputableLeaf for Value {
+    type LeafContext = u32;
+    type LeafOutput = u32;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        self.0 as u32 * context
+    }
+}
+#[derive(Debug, Clone, PartialEq, Arbitrary)]
+enum Op {
+    Sum,
+    Max,
+}
+impl<BA, LA, BM, LM> ComputableBranch<Value, BA, LA, BM, LM> for Op
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Value>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = u32;
+    type BranchOutput = u32;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Value>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Value, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        let children = controls.compute_all_both(context);
+        match self {
+            Op::Sum => children.sum(),
+            Op::Max => children.max().unwrap_or(0),
+        }
+    }
+}
+make_tree_aliases!(OpTree, Op, Value, u8, u16);
+
+/// Evaluate the tree before it is packed, to compare against `compute`.
+fn evaluate(branch: &ConstructableTreeBranch<Op, Value>, context: u32) -> u32 {
+    let children = branch
+        .leaves
+        .iter()
+        .map(|leaf| leaf.value.0 as u32 * context)
+        .chain(
+            branch
+                .sub_branches
+                .iter()
+                .map(|sub_branch| evaluate(sub_branch, context)),
+        );
+    match branch.value {
+        Op::Sum => children.sum(),
+        Op::Max => children.max().unwrap_or(0),
+    }
+}
+
+/// Deterministic bytes so failures are reproducible.
+fn seeded_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn fuzz_to_tree_and_compute() {
+    let mut packed = 0;
+    for seed in 0..500 {
+        let bytes = seeded_bytes(seed, (seed as usize % 8 + 1) * 16);
+        let construction =
+            ConstructableTreeBranch::<Op, Value>::arbitrary(&mut Unstructured::new(&bytes))
+                .unwrap();
+        assert!(construction.depth() <= ARBITRARY_MAX_DEPTH);
+        let fits = construction.check_capacity::<u8, u16>();
+        match construction
+            .clone()
+            .to_tree::<OpTreeBA, OpTreeLA, u8, u16>()
+        {
+            Ok(tree) => {
+                assert_eq!(fits, Ok(()));
+                assert_eq!(tree.to_constructable(), construction);
+                assert_eq!(tree.compute(&3), evaluate(&construction, 3));
+                assert_eq!(tree.compute(&3), tree.compute(&3));
+                packed += 1;
+            }
+            Err(error) => assert_eq!(fits, Err(error)),
+        }
+    }
+    // Make sure both outcomes were exercised.
+    assert!(0 < packed && packed < 500);
+}
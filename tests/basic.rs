@@ -1,13 +1,25 @@
+use std::ops::ControlFlow;
+
 use mask_tracked_array::{Mask, MaskTrackedArray};
 use tiny_expr_tree::{
-    BranchNode, ComputableBranch, ComputableLeaf, LeafNode, TinyExprTree,
-    alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+    BranchNode, BudgetExceeded, ChildSlot, ChildrenMask, ComputableBranch, ComputableLeaf,
+    LeafNode, TinyExprTree, ValidationError, Visitor,
+    alloc_gen::{
+        ConstructableTreeBranch, ConstructableTreeLeaf, ConstructionError, NodeRef, OwnedNode,
+        PathError, TraceEntry, TreeMeta, TreeStats, build_tree,
+    },
+    expr_tree, make_tree_aliases, static_tree,
 };
-#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum BooleanLeaf {
     True,
     False,
     InsertedValue,
+    /// Panics if computed.
+    Unreachable,
+    /// True when stored in an even leaf slot.
+    EvenSlot,
 }
 impl ComputableLeaf for BooleanLeaf {
     type LeafContext = bool;
@@ -17,13 +29,44 @@ impl ComputableLeaf for BooleanLeaf {
             Self::False => false,
             Self::True => true,
             Self::InsertedValue => *context,
+            Self::Unreachable => panic!("leaf should not have been computed"),
+            Self::EvenSlot => unreachable!("computed through compute_indexed"),
+        }
+    }
+    fn compute_indexed(&self, context: &Self::LeafContext, index: u32) -> Self::LeafOutput {
+        match self {
+            Self::EvenSlot => index.is_multiple_of(2),
+            _ => self.compute(context),
         }
     }
 }
-#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BooleanComparator {
     And,
     Or,
+    /// The first leaf implies the second.
+    Implies,
+    /// Like `And`, but false when nested deeper than the given depth.
+    AndWithin(usize),
+    /// True when more than half of the children are true.
+    Majority,
+    /// True at the first true child, without computing the rest.
+    Selector,
+    /// True when an odd number of children are true.
+    Xor,
+    /// Same as `Xor`, computed with a fold.
+    Parity,
+    /// The output of the last added sub-branch, or of the last added leaf if
+    /// there are no sub-branches.
+    Priority,
+    /// Computes the first sub-branch if it is an `And`, and otherwise the
+    /// first leaf unless it is `Unreachable`.
+    Peek,
+    /// True when the first true child is in the given slot.
+    FirstTrueAt(ChildSlot),
+    /// True when at least the given number of children are true.
+    AtLeast(u32),
 }
 impl<BA, LA, BM, LM> ComputableBranch<BooleanLeaf, BA, LA, BM, LM> for BooleanComparator
 where
@@ -34,12 +77,12 @@ where
 {
     type BranchContext = bool;
     type BranchOutput = bool;
-    fn compute<'a>(
+    fn compute<'a, H: tiny_expr_tree::ComputeHooks<Self::BranchOutput, BooleanLeaf>>(
         &self,
         context: &Self::BranchContext,
-        controls: tiny_expr_tree::BranchControls<'a, Self, BooleanLeaf, BA, LA, BM, LM>,
+        controls: tiny_expr_tree::BranchControls<'a, Self, BooleanLeaf, BA, LA, BM, LM, H>,
     ) -> Self::BranchOutput {
-        let out = match self {
+        match self {
             Self::And => controls
                 .compute_all_branches(context)
                 .chain(controls.compute_all_leaves(context))
@@ -50,8 +93,76 @@ where
                 .chain(controls.compute_all_leaves(context))
                 .inspect(|v| println!("Item was {}", v))
                 .any(std::convert::identity),
-        };
-        out
+            Self::Implies => {
+                let mut leaves = controls.compute_leaves_indexed(context, LM::ALL_SELECTED);
+                match (leaves.next(), leaves.next()) {
+                    (Some((first_index, first)), Some((second_index, second))) => {
+                        assert!(first_index < second_index);
+                        !first || second
+                    }
+                    _ => false,
+                }
+            }
+            Self::AndWithin(max) => controls.depth() <= *max && controls.compute_all(context),
+            Self::Majority => {
+                let mut outputs = [false; 24];
+                let written = controls.compute_children_into(context, &mut outputs);
+                assert_eq!(written, controls.child_count());
+                assert_eq!(
+                    written as u32,
+                    controls.filled_branch_count() + controls.filled_leaf_count()
+                );
+                outputs[..written].iter().filter(|v| **v).count() * 2 > written
+            }
+            Self::Selector => controls
+                .compute_children_while(context, |v| {
+                    if v {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                })
+                .is_some(),
+            Self::Xor => {
+                controls
+                    .compute_both_with(context, u8::from, u8::from)
+                    .sum::<u8>()
+                    % 2
+                    == 1
+            }
+            Self::Parity => controls.fold_children(context, false, |acc, v| acc ^ v),
+            Self::Priority => controls
+                .compute_branches_rev(context, BM::ALL_SELECTED)
+                .chain(controls.compute_leaves_rev(context, LM::ALL_SELECTED))
+                .next()
+                .unwrap_or(false),
+            Self::Peek => {
+                if controls.branch_value(0) == Some(&BooleanComparator::And) {
+                    controls.compute_all_branches(context).next().unwrap()
+                } else if controls.leaf_value(0) != Some(&BooleanLeaf::Unreachable) {
+                    controls.compute_all_leaves(context).next().unwrap_or(false)
+                } else {
+                    false
+                }
+            }
+            Self::FirstTrueAt(slot) => {
+                controls
+                    .compute_first_success(context, |v| *v)
+                    .is_some_and(|(found, _)| {
+                        match found {
+                            ChildSlot::Branch(index) => {
+                                assert!(controls.branch_in_slot(index).is_some())
+                            }
+                            ChildSlot::Leaf(index) => assert!(matches!(
+                                controls.leaf_in_slot(index),
+                                Some(BooleanLeaf::True | BooleanLeaf::InsertedValue)
+                            )),
+                        }
+                        found == *slot
+                    })
+            }
+            Self::AtLeast(k) => controls.count_true(context) >= *k,
+        }
     }
 }
 make_tree_aliases!(pub MiniTree, BooleanComparator, BooleanLeaf, u8, u16);
@@ -68,4 +179,1949 @@ fn basic() {
     let tree: MiniTree = construction.to_tree().unwrap();
     assert!(tree.compute(&true));
     assert!(!tree.compute(&false));
+    assert!(tree.compute_copy(true));
+    assert!(!tree.compute_copy(false));
+    let contexts = vec![true, false, false, true];
+    assert!(tree.compute_batch(&contexts).eq(contexts.iter().copied()));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn decompile_round_trip() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::Or);
+    construction.add_leaf(BooleanLeaf::False);
+    let mut first = ConstructableTreeBranch::new(BooleanComparator::And);
+    first.add_leaf(BooleanLeaf::True);
+    first.add_leaf(BooleanLeaf::InsertedValue);
+    let mut second = ConstructableTreeBranch::new(BooleanComparator::Or);
+    second.add_leaf(BooleanLeaf::InsertedValue);
+    second.add_branch(first.clone());
+    construction.add_branch(first);
+    construction.add_branch(second);
+    construction.add_leaf(BooleanLeaf::True);
+    let tree: MiniTree = construction.clone().to_tree().unwrap();
+    let decompiled = tree.to_constructable();
+    assert_eq!(decompiled, construction);
+    let rebuilt: MiniTree = decompiled.to_tree().unwrap();
+    assert_eq!(rebuilt.to_constructable(), construction);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn leaf_capacity_error() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::And);
+    for _ in 0..20 {
+        construction.add_leaf(BooleanLeaf::True);
+    }
+    let error = construction
+        .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+        .unwrap_err();
+    assert_eq!(
+        error,
+        ConstructionError::InsufficientLeafCapacity {
+            required: 20,
+            available: 16
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "insufficient leaf capacity: tree needs 20 leaf slots but only 16 are available"
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn capacity_check_excludes_root() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::And);
+    for _ in 0..8 {
+        construction.add_branch(ConstructableTreeBranch::new(BooleanComparator::Or));
+    }
+    assert_eq!(construction.check_capacity::<u8, u16>(), Ok(()));
+    assert!(
+        construction
+            .clone()
+            .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .is_ok()
+    );
+    construction.add_branch(ConstructableTreeBranch::new(BooleanComparator::Or));
+    assert_eq!(
+        construction.check_capacity::<u8, u16>(),
+        Err(ConstructionError::InsufficientBranchCapacity {
+            required: 9,
+            available: 8
+        })
+    );
+    assert_eq!(
+        construction
+            .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .unwrap_err(),
+        ConstructionError::InsufficientBranchCapacity {
+            required: 9,
+            available: 8
+        }
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn max_depth() {
+    let mut inner = ConstructableTreeBranch::new(BooleanComparator::And);
+    inner.add_leaf(BooleanLeaf::True);
+    let mut middle = ConstructableTreeBranch::new(BooleanComparator::Or);
+    middle.add_branch(inner);
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::And);
+    construction.add_branch(middle);
+    assert_eq!(construction.depth(), 3);
+    assert!(
+        construction
+            .clone()
+            .to_tree_with_max_depth::<MiniTreeBA, MiniTreeLA, u8, u16>(3)
+            .is_ok()
+    );
+    assert_eq!(
+        construction
+            .to_tree_with_max_depth::<MiniTreeBA, MiniTreeLA, u8, u16>(2)
+            .unwrap_err(),
+        ConstructionError::DepthExceeded { found: 3, max: 2 }
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn fluent_builder() {
+    let sub_tree = ConstructableTreeBranch::new(BooleanComparator::And)
+        .with_leaf(BooleanLeaf::True)
+        .with_leaf(BooleanLeaf::InsertedValue);
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(sub_tree.clone());
+    let mut expected = ConstructableTreeBranch::new(BooleanComparator::Or);
+    expected.add_leaf(BooleanLeaf::False);
+    expected.add_branch(sub_tree);
+    assert_eq!(construction, expected);
+    let tree: MiniTree = construction.to_tree().unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn add_branch_mut() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::Or);
+    construction.add_leaf(BooleanLeaf::False);
+    let child = construction.add_branch_mut(ConstructableTreeBranch::new(BooleanComparator::And));
+    child.add_leaf(BooleanLeaf::True);
+    child.add_leaf(BooleanLeaf::InsertedValue);
+    assert_eq!(construction.leaf_count(), 3);
+    let tree: MiniTree = construction.to_tree().unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn extend_leaves() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::And);
+    construction.extend_leaves([BooleanLeaf::True, BooleanLeaf::InsertedValue]);
+    construction.extend(core::iter::repeat_n(BooleanLeaf::True, 2));
+    assert_eq!(construction.leaf_count(), 4);
+    assert_eq!(construction.leaves[1].value, BooleanLeaf::InsertedValue);
+    let tree: MiniTree = construction.to_tree().unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn retain() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::And)
+        .with_leaf(BooleanLeaf::False)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::Or))
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::False),
+        );
+    construction.retain_leaves(|leaf| *leaf != BooleanLeaf::False);
+    construction.retain_branches(|branch| !branch.leaves.is_empty());
+    assert_eq!(construction.leaves.len(), 1);
+    assert_eq!(construction.sub_branches.len(), 1);
+    assert_eq!(construction.leaf_count(), 2);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn map_values() {
+    let raw = ConstructableTreeBranch::new("or")
+        .with_leaf(false)
+        .with_branch(ConstructableTreeBranch::new("and").with_leaf(true));
+    let construction = raw.map(
+        |op| match op {
+            "and" => BooleanComparator::And,
+            _ => BooleanComparator::Or,
+        },
+        |lit| {
+            if lit {
+                BooleanLeaf::True
+            } else {
+                BooleanLeaf::False
+            }
+        },
+    );
+    let expected = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True),
+        );
+    assert_eq!(construction, expected);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn try_map_values() {
+    fn lower_op(op: &str) -> Result<BooleanComparator, String> {
+        match op {
+            "and" => Ok(BooleanComparator::And),
+            "or" => Ok(BooleanComparator::Or),
+            other => Err(format!("unknown operator {other}")),
+        }
+    }
+    let lower_lit = |lit: bool| -> Result<BooleanLeaf, String> {
+        Ok(if lit {
+            BooleanLeaf::True
+        } else {
+            BooleanLeaf::False
+        })
+    };
+    let good = ConstructableTreeBranch::new("or")
+        .with_leaf(false)
+        .with_branch(ConstructableTreeBranch::new("and").with_leaf(true));
+    assert_eq!(
+        good.try_map(lower_op, lower_lit),
+        Ok(ConstructableTreeBranch::new(BooleanComparator::Or)
+            .with_leaf(BooleanLeaf::False)
+            .with_branch(
+                ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True)
+            ))
+    );
+    let bad = ConstructableTreeBranch::new("or")
+        .with_branch(ConstructableTreeBranch::new("xor").with_leaf(true));
+    assert_eq!(
+        bad.try_map(lower_op, lower_lit),
+        Err("unknown operator xor".to_string())
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn deep_tree_does_not_overflow() {
+    let chain = || {
+        let mut construction = ConstructableTreeBranch::new(BooleanComparator::And);
+        for _ in 1..100_000 {
+            construction =
+                ConstructableTreeBranch::new(BooleanComparator::And).with_branch(construction);
+        }
+        construction
+    };
+    let construction = chain();
+    assert_eq!(construction.branch_count(), 100_000);
+    let error = ConstructionError::InsufficientBranchCapacity {
+        required: 99_999,
+        available: 8,
+    };
+    assert_eq!(
+        construction
+            .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .unwrap_err(),
+        error
+    );
+    // Deduplicating skips the capacity check up front, so this walks the
+    // whole chain while packing until the slots run out.
+    assert_eq!(
+        chain()
+            .to_tree_dedup::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .unwrap_err(),
+        error
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn iter_dfs() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        )
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::And));
+    let nodes: Vec<_> = construction.iter_dfs().collect();
+    assert_eq!(
+        nodes,
+        [
+            NodeRef::Branch(&BooleanComparator::Or),
+            NodeRef::Branch(&BooleanComparator::And),
+            NodeRef::Leaf(&BooleanLeaf::True),
+            NodeRef::Leaf(&BooleanLeaf::InsertedValue),
+            NodeRef::Branch(&BooleanComparator::And),
+            NodeRef::Leaf(&BooleanLeaf::False),
+        ]
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn iter_leaves() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        );
+    let tree: MiniTree = construction.to_tree().unwrap();
+    let leaves: Vec<_> = tree.iter_leaves().collect();
+    assert_eq!(
+        leaves,
+        [
+            &BooleanLeaf::True,
+            &BooleanLeaf::InsertedValue,
+            &BooleanLeaf::False
+        ]
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn iter_branches() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::And))
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or)
+                .with_branch(ConstructableTreeBranch::new(BooleanComparator::And)),
+        );
+    let tree: MiniTree = construction.to_tree().unwrap();
+    let branches: Vec<_> = tree.iter_branches().collect();
+    assert_eq!(
+        branches,
+        [
+            &BooleanComparator::Or,
+            &BooleanComparator::And,
+            &BooleanComparator::And,
+            &BooleanComparator::Or
+        ]
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn tree_counts() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        );
+    let branch_count = construction.branch_count();
+    let leaf_count = construction.leaf_count();
+    let tree: MiniTree = construction.to_tree().unwrap();
+    assert_eq!(tree.branch_count(), branch_count);
+    assert_eq!(tree.leaf_count(), leaf_count);
+    assert_eq!(tree.node_count(), 5);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn clone_tree() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        );
+    let tree: MiniTree = construction.clone().to_tree().unwrap();
+    let cloned = tree.clone();
+    drop(tree);
+    assert_eq!(cloned.to_constructable(), construction);
+    assert!(cloned.compute(&true));
+    assert!(!cloned.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn tree_equality() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        );
+    let tree: MiniTree = construction.clone().to_tree().unwrap();
+    let rebuilt: MiniTree = tree.to_constructable().to_tree().unwrap();
+    assert_eq!(tree, rebuilt);
+    assert_eq!(tree, tree.clone());
+    let other: MiniTree = construction.with_leaf(BooleanLeaf::True).to_tree().unwrap();
+    assert_ne!(tree, other);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn depth() {
+    let leaf_only =
+        ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True);
+    assert_eq!(leaf_only.depth(), 1);
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::And))
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::Or).with_branch(leaf_only));
+    assert_eq!(construction.depth(), 3);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn indexed_leaves() {
+    let implies_inserted = ConstructableTreeBranch::new(BooleanComparator::Implies)
+        .with_leaf(BooleanLeaf::True)
+        .with_leaf(BooleanLeaf::InsertedValue);
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::And)
+        .with_leaf(BooleanLeaf::True)
+        .with_branch(implies_inserted)
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Implies)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .with_leaf(BooleanLeaf::False)
+        .to_tree()
+        .unwrap();
+    assert!(!tree.compute(&true));
+    assert!(tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn depth_in_controls() {
+    let nested = |max| {
+        ConstructableTreeBranch::new(BooleanComparator::AndWithin(max)).with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::AndWithin(max)).with_branch(
+                ConstructableTreeBranch::new(BooleanComparator::AndWithin(max)),
+            ),
+        )
+    };
+    let tree: MiniTree = nested(2).to_tree().unwrap();
+    assert!(tree.compute(&true));
+    let tree: MiniTree = nested(1).to_tree().unwrap();
+    assert!(!tree.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_children_into() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Majority)
+        .with_leaf(BooleanLeaf::True)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::False),
+        )
+        .to_tree()
+        .unwrap();
+    assert!(!tree.compute(&false));
+    assert!(tree.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_children_while() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Selector)
+        .with_leaf(BooleanLeaf::Unreachable)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::False),
+        )
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True),
+        )
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&false));
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Selector)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .with_leaf(BooleanLeaf::Unreachable)
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn mutate_in_place() {
+    let mut tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True),
+        )
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&false));
+    *tree.leaf_mut(0).unwrap() = BooleanLeaf::InsertedValue;
+    assert!(!tree.compute(&false));
+    assert!(tree.compute(&true));
+    *tree.branch_mut(0).unwrap() = BooleanComparator::Or;
+    *tree.root_mut() = BooleanComparator::And;
+    assert_eq!(
+        tree.to_constructable(),
+        ConstructableTreeBranch::new(BooleanComparator::And)
+            .with_leaf(BooleanLeaf::False)
+            .with_branch(
+                ConstructableTreeBranch::new(BooleanComparator::Or)
+                    .with_leaf(BooleanLeaf::InsertedValue)
+            )
+    );
+    assert!(tree.leaf_mut(5).is_none());
+    assert!(tree.branch_mut(1).is_none());
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn empty_root() {
+    let and: MiniTree = ConstructableTreeBranch::new(BooleanComparator::And)
+        .to_tree()
+        .unwrap();
+    assert!(and.compute(&false));
+    assert_eq!(and.node_count(), 1);
+    let or: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .to_tree()
+        .unwrap();
+    assert!(!or.compute(&true));
+    let selector: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Selector)
+        .to_tree()
+        .unwrap();
+    assert!(!selector.compute(&true));
+    let majority: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Majority)
+        .to_tree()
+        .unwrap();
+    assert!(!majority.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn empty_sub_branch() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::And))
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::Or))
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_both_with() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Xor)
+        .with_leaf(BooleanLeaf::True)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True),
+        )
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+mod public_aliases {
+    use super::{BooleanComparator, BooleanLeaf};
+    tiny_expr_tree::make_tree_aliases!(pub ReExportedTree, BooleanComparator, BooleanLeaf, u8, u8);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn public_aliases() {
+    use public_aliases::ReExportedTree;
+    let tree: ReExportedTree = ConstructableTreeBranch::new(BooleanComparator::And)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+mod several_aliases {
+    use super::{BooleanComparator, BooleanLeaf};
+    tiny_expr_tree::make_tree_aliases!(pub SmallTree, BooleanComparator, BooleanLeaf, u8, u8);
+    tiny_expr_tree::make_tree_aliases!(pub LargeTree, BooleanComparator, BooleanLeaf, u64, u128);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn several_aliases_in_one_module() {
+    use several_aliases::{LargeTree, LargeTreeBA, LargeTreeLA, SmallTree};
+    let construction =
+        ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::InsertedValue);
+    let small: SmallTree = construction.clone().to_tree().unwrap();
+    let large: LargeTree = construction
+        .to_tree::<LargeTreeBA, LargeTreeLA, u64, u128>()
+        .unwrap();
+    assert!(small.compute(&true));
+    assert!(large.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn expr_tree_macro() {
+    use BooleanComparator::*;
+    use BooleanLeaf::*;
+    let tree = expr_tree!(And { True, InsertedValue, Or { False }, });
+    let expected = ConstructableTreeBranch::new(And)
+        .with_leaf(True)
+        .with_leaf(InsertedValue)
+        .with_branch(ConstructableTreeBranch::new(Or).with_leaf(False));
+    assert_eq!(tree, expected);
+    let tree = expr_tree!(AndWithin(2) { Or {}, BooleanLeaf::True });
+    let expected = ConstructableTreeBranch::new(AndWithin(2))
+        .with_branch(ConstructableTreeBranch::new(Or))
+        .with_leaf(True);
+    assert_eq!(tree, expected);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_dot() {
+    let construction = ConstructableTreeBranch::new("or")
+        .with_leaf("false")
+        .with_branch(ConstructableTreeBranch::new("and").with_leaf("\"input\""));
+    assert_eq!(
+        construction.to_dot(),
+        r#"digraph {
+    n0 [label="or", shape=ellipse];
+    n1 [label="and", shape=ellipse];
+    n2 [label="\"input\"", shape=box];
+    n1 -> n2;
+    n0 -> n1;
+    n3 [label="false", shape=box];
+    n0 -> n3;
+}
+"#
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn pretty() {
+    let construction = ConstructableTreeBranch::new("or")
+        .with_leaf("false")
+        .with_branch(ConstructableTreeBranch::new("and").with_leaf("input"));
+    assert_eq!(construction.pretty(), "or\n  and\n    input\n  false\n");
+}
+
+#[cfg(all(feature = "alloc-gen", feature = "serde"))]
+#[test]
+fn serde_round_trip() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        )
+        .to_tree()
+        .unwrap();
+    let json = serde_json::to_string(&tree).unwrap();
+    let deserialized: MiniTree = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, tree);
+    assert!(deserialized.compute(&true));
+    assert!(!deserialized.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn reverse_order() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Priority)
+        .with_leaf(BooleanLeaf::False)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Priority)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::False),
+        )
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::True),
+        )
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn fold_children() {
+    let children = |root| {
+        ConstructableTreeBranch::new(root)
+            .with_leaf(BooleanLeaf::True)
+            .with_leaf(BooleanLeaf::InsertedValue)
+            .with_branch(
+                ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True),
+            )
+    };
+    let xor: MiniTree = children(BooleanComparator::Xor).to_tree().unwrap();
+    let parity: MiniTree = children(BooleanComparator::Parity).to_tree().unwrap();
+    for context in [true, false] {
+        assert_eq!(parity.compute(&context), xor.compute(&context));
+    }
+    assert!(parity.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn subtree() {
+    let first = ConstructableTreeBranch::new(BooleanComparator::And)
+        .with_leaf(BooleanLeaf::True)
+        .with_leaf(BooleanLeaf::InsertedValue);
+    let second = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(first.clone());
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Xor)
+        .with_leaf(BooleanLeaf::True)
+        .with_branch(second.clone())
+        .to_tree()
+        .unwrap();
+    let subtrees: Vec<_> = (0..8)
+        .filter_map(|index| tree.subtree(index))
+        .map(|subtree| subtree.to_constructable())
+        .collect();
+    assert_eq!(subtrees, vec![first, second.clone()]);
+    assert!(tree.subtree(2).is_none());
+    let expected: MiniTree = second.to_tree().unwrap();
+    assert_eq!(tree.subtree(1).unwrap(), expected);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn subtree_keeps_shared_branches() {
+    let level = |inner: ConstructableTreeBranch<BooleanComparator, BooleanLeaf>| {
+        ConstructableTreeBranch::new(BooleanComparator::Or)
+            .with_branch(inner.clone())
+            .with_branch(ConstructableTreeBranch::new(BooleanComparator::Xor).with_branch(inner))
+    };
+    let shared = level(level(
+        ConstructableTreeBranch::new(BooleanComparator::And)
+            .with_leaf(BooleanLeaf::InsertedValue)
+            .with_leaf(BooleanLeaf::True),
+    ));
+    // Written out, the shared branch needs 10 slots, but only has 8.
+    let expanded: Result<MiniTree, _> = shared.clone().to_tree();
+    assert!(expanded.is_err());
+    // The first branch takes the lowest slots, so the subtree has to move.
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Xor)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::False),
+        )
+        .with_branch(shared.clone())
+        .to_tree_dedup()
+        .unwrap();
+    let subtree = (0..8)
+        .filter_map(|index| tree.subtree(index))
+        .find(|subtree| subtree.to_constructable() == shared)
+        .unwrap();
+    assert_eq!(subtree.branch_count(), 5);
+    let packed: MiniTree = shared.to_tree_dedup().unwrap();
+    assert_eq!(subtree, packed);
+    for context in [false, true] {
+        assert_eq!(subtree.compute(&context), tree.compute(&context));
+    }
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn lookup_by_path() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Xor)
+        .with_leaf(BooleanLeaf::True)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or)
+                .with_leaf(BooleanLeaf::False)
+                .with_branch(
+                    ConstructableTreeBranch::new(BooleanComparator::And)
+                        .with_leaf(BooleanLeaf::True)
+                        .with_leaf(BooleanLeaf::InsertedValue),
+                ),
+        )
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::Parity))
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree.branch_at_path(&[]), Some(&BooleanComparator::Xor));
+    assert_eq!(tree.branch_at_path(&[0]), Some(&BooleanComparator::Or));
+    assert_eq!(tree.branch_at_path(&[1]), Some(&BooleanComparator::Parity));
+    assert_eq!(tree.branch_at_path(&[0, 0]), Some(&BooleanComparator::And));
+    assert_eq!(tree.branch_at_path(&[2]), None);
+    assert_eq!(tree.branch_at_path(&[1, 0]), None);
+    assert_eq!(tree.leaf_at_path(&[0]), Some(&BooleanLeaf::True));
+    assert_eq!(
+        tree.leaf_at_path(&[0, 0, 1]),
+        Some(&BooleanLeaf::InsertedValue)
+    );
+    assert_eq!(tree.leaf_at_path(&[0, 0]), Some(&BooleanLeaf::False));
+    assert_eq!(tree.leaf_at_path(&[0, 1]), None);
+    assert_eq!(tree.leaf_at_path(&[]), None);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn equal_branches_hash_equal() {
+    use std::hash::BuildHasher;
+    let hasher = std::collections::hash_map::RandomState::new();
+    let tree = || expr_tree!(BooleanComparator::And { BooleanLeaf::True, BooleanComparator::Or { BooleanLeaf::False } });
+    let decompiled = tree()
+        .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+        .unwrap()
+        .to_constructable();
+    assert_eq!(tree(), decompiled);
+    assert_eq!(hasher.hash_one(tree()), hasher.hash_one(decompiled));
+    // Leaves and sub-branches are kept in separate lists, so only the order
+    // within each list matters.
+    let reordered = expr_tree!(BooleanComparator::And { BooleanComparator::Or { BooleanLeaf::False }, BooleanLeaf::True });
+    assert_eq!(reordered, tree());
+    let swapped = expr_tree!(BooleanComparator::And { BooleanLeaf::False, BooleanLeaf::True });
+    assert_ne!(
+        swapped,
+        expr_tree!(BooleanComparator::And { BooleanLeaf::True, BooleanLeaf::False })
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_tree_with_stats() {
+    let (tree, stats): (MiniTree, _) = expr_tree!(BooleanComparator::And {
+        BooleanLeaf::True,
+        BooleanComparator::Or { BooleanLeaf::False, BooleanLeaf::True },
+        BooleanComparator::Xor {},
+    })
+    .to_tree_with_stats()
+    .unwrap();
+    assert_eq!(
+        stats,
+        TreeStats {
+            branches_used: 2,
+            leaves_used: 3,
+            branch_capacity: 8,
+            leaf_capacity: 16,
+        }
+    );
+    assert_eq!(stats.spare_branches(), 6);
+    assert_eq!(stats.spare_leaves(), 13);
+    assert_eq!(stats.branches_used + 1, tree.branch_count());
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_tree_dedup() {
+    let guard =
+        || expr_tree!(BooleanComparator::And { BooleanLeaf::True, BooleanLeaf::InsertedValue });
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_branch(guard())
+        .with_branch(guard())
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Xor)
+                .with_leaf(BooleanLeaf::False)
+                .with_branch(guard()),
+        );
+    let plain: MiniTree = construction.clone().to_tree().unwrap();
+    let dedup: MiniTree = construction.clone().to_tree_dedup().unwrap();
+    // The repeated guard under `Or` still needs its own slot, but the one
+    // under `Xor` reuses the first.
+    assert_eq!(plain.branch_count(), 5);
+    assert_eq!(dedup.branch_count(), 4);
+    assert_eq!(plain.leaf_count(), 7);
+    assert_eq!(dedup.leaf_count(), 3);
+    assert_eq!(dedup.to_constructable(), construction);
+    for context in [true, false] {
+        assert_eq!(dedup.compute(&context), plain.compute(&context));
+    }
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_tree_dedup_saves_capacity() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::Parity);
+    for _ in 0..5 {
+        construction.add_branch(expr_tree!(BooleanComparator::Or {
+            BooleanComparator::And { BooleanLeaf::True }
+        }));
+    }
+    assert!(matches!(
+        construction
+            .clone()
+            .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>(),
+        Err(ConstructionError::InsufficientBranchCapacity { .. })
+    ));
+    let tree: MiniTree = construction.clone().to_tree_dedup().unwrap();
+    assert_eq!(tree.branch_count(), 7);
+    assert_eq!(tree.to_constructable(), construction);
+    assert!(tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_tree_sorted() {
+    let rank = |leaf: &BooleanLeaf| match leaf {
+        BooleanLeaf::True => 0,
+        BooleanLeaf::False => 1,
+        BooleanLeaf::InsertedValue => 2,
+        BooleanLeaf::Unreachable => 3,
+        BooleanLeaf::EvenSlot => 4,
+    };
+    let sorted = |construction: ConstructableTreeBranch<_, _>| -> MiniTree {
+        construction
+            .to_tree_sorted(|a, b| rank(a).cmp(&rank(b)))
+            .unwrap()
+    };
+    let first = sorted(expr_tree!(BooleanComparator::And {
+        BooleanLeaf::InsertedValue,
+        BooleanLeaf::True,
+        BooleanComparator::Or { BooleanLeaf::False, BooleanLeaf::True },
+    }));
+    let second = sorted(expr_tree!(BooleanComparator::And {
+        BooleanLeaf::True,
+        BooleanLeaf::InsertedValue,
+        BooleanComparator::Or { BooleanLeaf::True, BooleanLeaf::False },
+    }));
+    assert_eq!(first, second);
+    let expected: MiniTree = expr_tree!(BooleanComparator::And {
+        BooleanLeaf::True,
+        BooleanLeaf::InsertedValue,
+        BooleanComparator::Or { BooleanLeaf::True, BooleanLeaf::False },
+    })
+    .to_tree()
+    .unwrap();
+    assert_eq!(first, expected);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn merge_under() {
+    let first =
+        expr_tree!(BooleanComparator::Or { BooleanLeaf::InsertedValue, BooleanLeaf::False });
+    let second = expr_tree!(BooleanComparator::And { BooleanLeaf::True });
+    let merged = ConstructableTreeBranch::merge_under(
+        BooleanComparator::And,
+        vec![first.clone(), second.clone()],
+    );
+    assert_eq!(
+        merged,
+        ConstructableTreeBranch::new(BooleanComparator::And)
+            .with_branch(first)
+            .with_branch(second)
+    );
+    let tree: MiniTree = merged.to_tree().unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn count_matching() {
+    let construction = expr_tree!(BooleanComparator::And {
+        BooleanLeaf::InsertedValue,
+        BooleanLeaf::True,
+        BooleanComparator::Or { BooleanLeaf::InsertedValue, BooleanComparator::And {} },
+    });
+    let is_placeholder = |leaf: &BooleanLeaf| *leaf == BooleanLeaf::InsertedValue;
+    assert_eq!(construction.count_leaves(is_placeholder), 2);
+    assert_eq!(
+        construction.count_leaves(|_| true),
+        construction.leaf_count()
+    );
+    assert_eq!(
+        construction.count_branches(|branch| *branch == BooleanComparator::And),
+        2
+    );
+    assert_eq!(construction.count_branches(|_| false), 0);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn static_tree_macro() {
+    use BooleanComparator::*;
+    use BooleanLeaf::*;
+    let tree: MiniTree = static_tree!(u8, u16; And { True, InsertedValue, Or { False, True } });
+    let expected: MiniTree = expr_tree!(And { True, InsertedValue, Or { False, True } })
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree, expected);
+    // Exactly eight sub-branches and sixteen leaves still fit.
+    let full: MiniTree = static_tree!(u8, u16; Parity {
+        Or { True, True }, Or { True, True }, Or { True, True }, Or { True, True },
+        Or { True, True }, Or { True, True }, Or { True, True }, Or { True, True },
+    });
+    assert_eq!(full.branch_count(), 9);
+    assert_eq!(full.leaf_count(), 16);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_all_and_any() {
+    let all: MiniTree = expr_tree!(BooleanComparator::AndWithin(8) {
+        BooleanLeaf::True,
+        BooleanLeaf::InsertedValue,
+    })
+    .to_tree()
+    .unwrap();
+    assert!(all.compute(&true));
+    assert!(!all.compute(&false));
+    // `Unreachable` would panic if `AndWithin` did not stop at `False`.
+    let short_circuit: MiniTree = expr_tree!(BooleanComparator::AndWithin(8) {
+        BooleanLeaf::False,
+        BooleanLeaf::Unreachable,
+    })
+    .to_tree()
+    .unwrap();
+    assert!(!short_circuit.compute(&true));
+    let empty: MiniTree = ConstructableTreeBranch::new(BooleanComparator::AndWithin(8))
+        .to_tree()
+        .unwrap();
+    assert!(empty.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn peek_child_values() {
+    let tree: MiniTree = expr_tree!(BooleanComparator::Peek {
+        BooleanComparator::And { BooleanLeaf::InsertedValue },
+        BooleanLeaf::Unreachable,
+    })
+    .to_tree()
+    .unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+    let tree: MiniTree = expr_tree!(BooleanComparator::Peek {
+        BooleanComparator::Or { BooleanLeaf::Unreachable },
+        BooleanLeaf::Unreachable,
+        BooleanLeaf::True,
+    })
+    .to_tree()
+    .unwrap();
+    assert!(!tree.compute(&true));
+    let tree: MiniTree = expr_tree!(BooleanComparator::Peek { BooleanLeaf::True })
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_tree_checked() {
+    let construction = expr_tree!(BooleanComparator::And {
+        BooleanLeaf::True,
+        BooleanComparator::Or { BooleanComparator::Xor {} },
+    });
+    let error = construction
+        .clone()
+        .to_tree_checked::<MiniTreeBA, MiniTreeLA, u8, u16>()
+        .unwrap_err();
+    assert_eq!(error, ConstructionError::EmptyBranch { depth: 3 });
+    assert_eq!(
+        error.to_string(),
+        "empty branch: a branch at depth 3 has no children"
+    );
+    // The unchecked conversion still accepts it.
+    assert!(
+        construction
+            .to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .is_ok()
+    );
+    let tree: MiniTree = expr_tree!(BooleanComparator::And { BooleanLeaf::True })
+        .to_tree_checked()
+        .unwrap();
+    assert!(tree.compute(&false));
+    assert_eq!(
+        ConstructableTreeBranch::<BooleanComparator, BooleanLeaf>::new(BooleanComparator::Or)
+            .to_tree_checked::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .unwrap_err(),
+        ConstructionError::EmptyBranch { depth: 1 }
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn visitor() {
+    /// Writes the tree in the same layout `expr_tree!` takes.
+    #[derive(Default)]
+    struct Printer {
+        out: String,
+        open: Vec<u32>,
+    }
+    impl Printer {
+        fn child(&mut self) {
+            if let Some(remaining) = self.open.last_mut() {
+                *remaining -= 1;
+            }
+        }
+        fn close(&mut self) {
+            while self.open.last() == Some(&0) {
+                self.open.pop();
+                self.out.push_str(" }");
+            }
+        }
+    }
+    impl Visitor<BooleanComparator, BooleanLeaf, u8, u16> for Printer {
+        fn visit_branch(&mut self, branch: &BooleanComparator, mask: ChildrenMask<u8, u16>) {
+            self.child();
+            self.out.push_str(&format!(" {branch:?} {{"));
+            self.open
+                .push(mask.branch_mask.count_ones() + mask.leaf_mask.count_ones());
+            self.close();
+        }
+        fn visit_leaf(&mut self, leaf: &BooleanLeaf) {
+            self.child();
+            self.out.push_str(&format!(" {leaf:?}"));
+            self.close();
+        }
+    }
+    let tree: MiniTree = expr_tree!(BooleanComparator::And {
+        BooleanLeaf::True,
+        BooleanComparator::Or { BooleanLeaf::False, BooleanComparator::Xor {} },
+        BooleanLeaf::InsertedValue,
+    })
+    .to_tree()
+    .unwrap();
+    let mut printer = Printer::default();
+    tree.accept(&mut printer);
+    assert_eq!(
+        printer.out,
+        " And { Or { Xor { } False } True InsertedValue }"
+    );
+    /// Only counts leaves, relying on the default `visit_branch`.
+    struct Leaves(usize);
+    impl<B, L, BM, LM> Visitor<B, L, BM, LM> for Leaves {
+        fn visit_leaf(&mut self, _: &L) {
+            self.0 += 1;
+        }
+    }
+    let mut leaves = Leaves(0);
+    tree.accept(&mut leaves);
+    assert_eq!(leaves.0, tree.leaf_count());
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn from_parts() {
+    let mask = |branch_mask, leaf_mask| ChildrenMask {
+        branch_mask,
+        leaf_mask,
+    };
+    let parts = || {
+        let branches = MiniTreeBA::new();
+        let leaves = MiniTreeLA::new();
+        assert!(
+            leaves
+                .insert(0, LeafNode::new(BooleanLeaf::InsertedValue))
+                .is_none()
+        );
+        assert!(leaves.insert(1, LeafNode::new(BooleanLeaf::True)).is_none());
+        assert!(
+            branches
+                .insert(0, BranchNode::new(BooleanComparator::Or, mask(0, 0b11)))
+                .is_none()
+        );
+        (branches, leaves)
+    };
+    let (branches, leaves) = parts();
+    let root = BranchNode::new(BooleanComparator::And, mask(0b1, 0b10));
+    let tree = MiniTree::from_parts(root.clone(), branches, leaves).unwrap();
+    let expected: MiniTree = expr_tree!(BooleanComparator::And {
+        BooleanComparator::Or { BooleanLeaf::InsertedValue, BooleanLeaf::True },
+        BooleanLeaf::True,
+    })
+    .to_tree_dedup()
+    .unwrap();
+    assert_eq!(tree, expected);
+    assert!(tree.compute(&false));
+
+    let (branches, leaves) = parts();
+    let dangling = BranchNode::new(BooleanComparator::And, mask(0b10, 0));
+    assert_eq!(
+        MiniTree::from_parts(dangling, branches, leaves).unwrap_err(),
+        ValidationError::MissingBranch {
+            branch: None,
+            slot: 1
+        }
+    );
+    let (branches, leaves) = parts();
+    let dangling = BranchNode::new(BooleanComparator::And, mask(0, 0b100));
+    assert_eq!(
+        MiniTree::from_parts(dangling, branches, leaves).unwrap_err(),
+        ValidationError::MissingLeaf {
+            branch: None,
+            slot: 2
+        }
+    );
+    let (branches, leaves) = parts();
+    assert!(
+        branches
+            .insert(1, BranchNode::new(BooleanComparator::Or, mask(0b100, 0)))
+            .is_none()
+    );
+    assert!(
+        branches
+            .insert(2, BranchNode::new(BooleanComparator::Or, mask(0b10, 0)))
+            .is_none()
+    );
+    assert_eq!(
+        MiniTree::from_parts(root, branches, leaves).unwrap_err(),
+        ValidationError::Cycle
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn root_accessors() {
+    let mut tree: MiniTree = expr_tree!(BooleanComparator::Xor {
+        BooleanLeaf::True,
+        BooleanLeaf::False,
+        BooleanComparator::Or { BooleanLeaf::True },
+    })
+    .to_tree()
+    .unwrap();
+    assert_eq!(tree.root(), &BooleanComparator::Xor);
+    // The `Or` branch took leaf slot 0 before the root's own leaves.
+    assert_eq!(
+        tree.root_mask(),
+        ChildrenMask {
+            branch_mask: 0b1,
+            leaf_mask: 0b110,
+        }
+    );
+    *tree.root_mut() = BooleanComparator::And;
+    assert_eq!(tree.root(), &BooleanComparator::And);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn node_accessors() {
+    let mask = ChildrenMask {
+        branch_mask: 0b10u8,
+        leaf_mask: 0b1u16,
+    };
+    let branch = BranchNode::new(BooleanComparator::Or, mask);
+    assert_eq!(branch.value(), &BooleanComparator::Or);
+    assert_eq!(branch.mask(), mask);
+    let leaf = LeafNode::new(BooleanLeaf::False);
+    assert_eq!(leaf.value(), &BooleanLeaf::False);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn bind_leaves_in_slot_order() {
+    type Branches = mask_tracked_array::MaskTrackedArrayU8<BranchNode<&'static str, u8, u8>>;
+    type Leaves = mask_tracked_array::MaskTrackedArrayU8<LeafNode<usize>>;
+    let mut construction = ConstructableTreeBranch::new("root")
+        .with_leaf(usize::MAX)
+        .with_branch(
+            ConstructableTreeBranch::new("first")
+                .with_leaf(usize::MAX)
+                .with_branch(ConstructableTreeBranch::new("nested").with_leaf(usize::MAX)),
+        )
+        .with_branch(ConstructableTreeBranch::new("second").with_leaf(usize::MAX));
+    let mut next = 0;
+    construction.bind_leaves(|leaf| {
+        *leaf = next;
+        next += 1;
+    });
+    assert_eq!(next, 4);
+    let tree: TinyExprTree<_, _, Branches, Leaves, u8, u8> = construction.to_tree().unwrap();
+    assert!(tree.iter_leaves().copied().eq(0..4));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_traced() {
+    let tree: MiniTree = expr_tree!(BooleanComparator::Xor {
+        BooleanLeaf::True,
+        BooleanComparator::Selector { BooleanLeaf::InsertedValue, BooleanLeaf::Unreachable },
+    })
+    .to_tree()
+    .unwrap();
+    let (output, trace) = tree.compute_traced(&true);
+    assert_eq!(output, tree.compute(&true));
+    // `Selector` stops at its first true leaf, so the `Unreachable` leaf in
+    // slot 1 never shows up.
+    assert_eq!(
+        trace,
+        vec![
+            TraceEntry::Leaf {
+                index: 0,
+                output: true
+            },
+            TraceEntry::Branch {
+                index: 0,
+                output: true
+            },
+            TraceEntry::Leaf {
+                index: 2,
+                output: true
+            },
+            TraceEntry::Root { output: false },
+        ]
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn leaves_see_their_slot() {
+    let tree: MiniTree = expr_tree!(BooleanComparator::Xor {
+        BooleanLeaf::EvenSlot,
+        BooleanComparator::And { BooleanLeaf::EvenSlot, BooleanLeaf::True },
+        BooleanLeaf::EvenSlot,
+    })
+    .to_tree()
+    .unwrap();
+    // The `And` leaves take slots 0 and 1, then the root's leaves 2 and 3.
+    let (_, trace) = tree.compute_traced(&false);
+    let leaves: Vec<_> = trace
+        .into_iter()
+        .filter_map(|entry| match entry {
+            TraceEntry::Leaf { index, output } => Some((index, output)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(leaves, vec![(0, true), (1, true), (2, true), (3, false)]);
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn add_leaf_node() {
+    let mut by_value = ConstructableTreeBranch::new(BooleanComparator::And);
+    by_value.add_leaf(BooleanLeaf::True);
+    let mut by_node = ConstructableTreeBranch::new(BooleanComparator::And);
+    by_node.add_leaf_node(ConstructableTreeLeaf::new(BooleanLeaf::True));
+    assert_eq!(by_value, by_node);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn short_circuit_skips_later_branches() {
+    // `Or` stops at the first true sub-branch, so the second sub-branch and
+    // its `Unreachable` leaf are never computed.
+    let tree: MiniTree = expr_tree!(BooleanComparator::Or {
+        BooleanComparator::And { BooleanLeaf::True },
+        BooleanComparator::And { BooleanLeaf::Unreachable },
+        BooleanLeaf::Unreachable,
+    })
+    .to_tree()
+    .unwrap();
+    assert!(tree.compute(&false));
+    let (_, trace) = tree.compute_traced(&false);
+    assert!(
+        !trace
+            .iter()
+            .any(|entry| matches!(entry, TraceEntry::Branch { index: 1, .. }))
+    );
+    assert!(
+        !trace
+            .iter()
+            .any(|entry| matches!(entry, TraceEntry::Leaf { index: 1.., .. }))
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn default_branch() {
+    // `BooleanLeaf` has no `Default`, only the branch value needs one.
+    let construction = ConstructableTreeBranch::<u8, BooleanLeaf>::default();
+    assert_eq!(construction, ConstructableTreeBranch::new(0));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn rebalance_flattens_chains() {
+    // A right-leaning `And` chain as a parser would produce it, with an `Or`
+    // in the middle that must stay a separate branch.
+    let mut construction =
+        ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::InsertedValue);
+    for index in 0..10 {
+        let mut parent = ConstructableTreeBranch::new(BooleanComparator::And);
+        if index == 5 {
+            parent.add_branch(
+                ConstructableTreeBranch::new(BooleanComparator::Or)
+                    .with_leaf(BooleanLeaf::False)
+                    .with_branch(
+                        ConstructableTreeBranch::new(BooleanComparator::And)
+                            .with_leaf(BooleanLeaf::True),
+                    ),
+            );
+        }
+        construction = parent
+            .with_leaf(BooleanLeaf::True)
+            .with_branch(construction);
+    }
+    assert_eq!(construction.depth(), 11);
+    let before = construction.clone();
+    construction.rebalance(|parent, child| {
+        parent == child && matches!(parent, BooleanComparator::And | BooleanComparator::Or)
+    });
+    assert_eq!(construction.depth(), 3);
+    assert_eq!(construction.sub_branches.len(), 1);
+    assert_eq!(construction.leaf_count(), before.leaf_count());
+    assert_eq!(construction.branch_count(), 3);
+    assert!(before.to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>().is_err());
+    let tree: MiniTree = construction.to_tree().unwrap();
+    assert!(tree.compute(&true));
+    assert!(!tree.compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn branches_and_leaves_full() {
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::Or);
+    for _ in 0..7 {
+        construction.add_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True),
+        );
+    }
+    let tree: MiniTree = construction.clone().to_tree().unwrap();
+    assert!(!tree.branches_full());
+    assert!(!tree.leaves_full());
+    construction.add_branch(ConstructableTreeBranch::new(BooleanComparator::And));
+    construction.extend_leaves(std::iter::repeat_n(BooleanLeaf::False, 9));
+    let tree: MiniTree = construction.to_tree().unwrap();
+    assert!(tree.branches_full());
+    assert!(tree.leaves_full());
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_children() {
+    // The root `Xor` is never run, only its children are computed.
+    let tree: MiniTree = expr_tree!(BooleanComparator::Xor {
+        BooleanComparator::And { BooleanLeaf::True, BooleanLeaf::InsertedValue },
+        BooleanLeaf::False,
+        BooleanLeaf::InsertedValue,
+    })
+    .to_tree()
+    .unwrap();
+    assert!(tree.compute_children(&true).eq([true, false, true]));
+    assert!(tree.compute_children(&false).eq([false, false, false]));
+    // Children are computed lazily, so stopping early skips the rest.
+    let tree: MiniTree = expr_tree!(BooleanComparator::And {
+        BooleanLeaf::True,
+        BooleanLeaf::Unreachable,
+    })
+    .to_tree()
+    .unwrap();
+    assert_eq!(tree.compute_children(&false).next(), Some(true));
+}
+
+#[cfg(all(feature = "alloc-gen", feature = "serde"))]
+#[test]
+fn validate_loaded_tree() {
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        )
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree.validate(), Ok(()));
+    // Masks are edited in the serialized text, as if the stored copy were
+    // corrupted.
+    let json = serde_json::to_string(&tree).unwrap();
+    let load = |from: &str, to: &str| {
+        assert!(json.contains(from));
+        serde_json::from_str::<MiniTree>(&json.replace(from, to))
+            .unwrap()
+            .validate()
+    };
+    assert_eq!(
+        load(r#""leaf_mask":4}"#, r#""leaf_mask":12}"#),
+        Err(ValidationError::MissingLeaf {
+            branch: None,
+            slot: 3
+        })
+    );
+    assert_eq!(
+        load(r#""branch_mask":0"#, r#""branch_mask":2"#),
+        Err(ValidationError::MissingBranch {
+            branch: Some(0),
+            slot: 1
+        })
+    );
+    assert_eq!(
+        load(r#""branch_mask":0"#, r#""branch_mask":1"#),
+        Err(ValidationError::Cycle)
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_with_leaves() {
+    let tree: MiniTree = expr_tree!(BooleanComparator::Or {
+        BooleanComparator::And { BooleanLeaf::InsertedValue, BooleanLeaf::True },
+        BooleanLeaf::True,
+        BooleanLeaf::Unreachable,
+    })
+    .to_tree()
+    .unwrap();
+    let (output, leaves) = tree.compute_with_leaves(&false);
+    assert!(output);
+    // `And` stops at its false first leaf and `Or` at its first true leaf.
+    assert_eq!(leaves, vec![(0, false), (2, true)]);
+    let (output, leaves) = tree.compute_with_leaves(&true);
+    assert!(output);
+    assert_eq!(leaves, vec![(0, true), (1, true)]);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn into_iter_matches_iter_dfs() {
+    let construction = ConstructableTreeBranch::new(BooleanComparator::Or)
+        .with_leaf(BooleanLeaf::False)
+        .with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::And)
+                .with_leaf(BooleanLeaf::True)
+                .with_leaf(BooleanLeaf::InsertedValue),
+        )
+        .with_branch(ConstructableTreeBranch::new(BooleanComparator::And));
+    let borrowed: Vec<_> = construction
+        .iter_dfs()
+        .map(|node| match node {
+            NodeRef::Branch(branch) => OwnedNode::Branch(branch.clone()),
+            NodeRef::Leaf(leaf) => OwnedNode::Leaf(leaf.clone()),
+        })
+        .collect();
+    let mut owned = Vec::new();
+    for node in construction {
+        owned.push(node);
+    }
+    assert_eq!(owned, borrowed);
+    assert_eq!(owned[0], OwnedNode::Branch(BooleanComparator::Or));
+    assert_eq!(owned[5], OwnedNode::Leaf(BooleanLeaf::False));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn to_tree_with_meta() {
+    // Each node carries the config line it came from.
+    let construction = ConstructableTreeBranch::new((BooleanComparator::Or, 1))
+        .with_leaf((BooleanLeaf::False, 2))
+        .with_branch(
+            ConstructableTreeBranch::new((BooleanComparator::And, 3))
+                .with_leaf((BooleanLeaf::True, 4))
+                .with_branch(
+                    ConstructableTreeBranch::new((BooleanComparator::Xor, 5))
+                        .with_leaf((BooleanLeaf::InsertedValue, 6)),
+                ),
+        )
+        .with_branch(
+            ConstructableTreeBranch::new((BooleanComparator::And, 7))
+                .with_leaf((BooleanLeaf::True, 8)),
+        );
+    let expected: MiniTree = construction
+        .clone()
+        .map(|(branch, _)| branch, |(leaf, _)| leaf)
+        .to_tree()
+        .unwrap();
+    let (tree, meta): (MiniTree, _) = construction.to_tree_with_meta().unwrap();
+    assert_eq!(tree, expected);
+    assert_eq!(
+        meta,
+        TreeMeta {
+            root: 1,
+            branches: vec![5, 3, 7],
+            leaves: vec![6, 4, 8, 2],
+        }
+    );
+    assert_eq!(tree.iter_leaves().next(), Some(&BooleanLeaf::InsertedValue));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn is_trivial() {
+    let single = |tree: ConstructableTreeBranch<_, _>| {
+        tree.to_tree::<MiniTreeBA, MiniTreeLA, u8, u16>()
+            .unwrap()
+            .is_trivial()
+    };
+    assert!(single(
+        ConstructableTreeBranch::new(BooleanComparator::And).with_leaf(BooleanLeaf::True)
+    ));
+    assert!(!single(ConstructableTreeBranch::new(
+        BooleanComparator::And
+    )));
+    assert!(!single(
+        ConstructableTreeBranch::new(BooleanComparator::And)
+            .with_leaf(BooleanLeaf::True)
+            .with_leaf(BooleanLeaf::False)
+    ));
+    assert!(!single(
+        ConstructableTreeBranch::new(BooleanComparator::And).with_branch(
+            ConstructableTreeBranch::new(BooleanComparator::Or).with_leaf(BooleanLeaf::True)
+        )
+    ));
+    // A single leaf still sits under a root branch, which takes no slot.
+    let tree: MiniTree = ConstructableTreeBranch::new(BooleanComparator::And)
+        .with_leaf(BooleanLeaf::InsertedValue)
+        .to_tree()
+        .unwrap();
+    assert!(tree.is_trivial());
+    assert_eq!(tree.branch_count(), 1);
+    assert!(tree.compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn slot_layout_is_stable() {
+    // External tools depend on these exact slots, see `to_tree`. Branch
+    // slots: `Xor` 0, inner `Or` 1, `And` 2, `Parity` 3.
+    let tree: MiniTree = expr_tree!(BooleanComparator::Or {
+        BooleanLeaf::False,
+        BooleanComparator::And {
+            BooleanLeaf::True,
+            BooleanComparator::Xor { BooleanLeaf::InsertedValue, BooleanLeaf::True },
+            BooleanComparator::Or { BooleanLeaf::False },
+        },
+        BooleanComparator::Parity { BooleanLeaf::Unreachable },
+    })
+    .to_tree()
+    .unwrap();
+    assert!(tree.iter_branches().eq([
+        &BooleanComparator::Or,
+        &BooleanComparator::Xor,
+        &BooleanComparator::Or,
+        &BooleanComparator::And,
+        &BooleanComparator::Parity,
+    ]));
+    assert!(tree.iter_leaves().eq([
+        &BooleanLeaf::InsertedValue,
+        &BooleanLeaf::True,
+        &BooleanLeaf::False,
+        &BooleanLeaf::True,
+        &BooleanLeaf::Unreachable,
+        &BooleanLeaf::False,
+    ]));
+    // Every branch mask in visiting order: root, `And`, `Xor`, `Or`, `Parity`.
+    struct Masks(Vec<ChildrenMask<u8, u16>>);
+    impl Visitor<BooleanComparator, BooleanLeaf, u8, u16> for Masks {
+        fn visit_branch(&mut self, _: &BooleanComparator, mask: ChildrenMask<u8, u16>) {
+            self.0.push(mask);
+        }
+    }
+    let mut masks = Masks(Vec::new());
+    tree.accept(&mut masks);
+    let masks: Vec<_> = masks
+        .0
+        .into_iter()
+        .map(|mask| (mask.branch_mask, mask.leaf_mask))
+        .collect();
+    assert_eq!(
+        masks,
+        [
+            (0b1100, 0b100000),
+            (0b0011, 0b001000),
+            (0, 0b000011),
+            (0, 0b000100),
+            (0, 0b010000),
+        ]
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn all_selected_skips_empty_slots() {
+    // Five of the sixteen leaf slots are filled; `ALL_SELECTED` must not
+    // reach the other eleven.
+    let mut construction = ConstructableTreeBranch::new(BooleanComparator::And);
+    construction.extend_leaves(std::iter::repeat_n(BooleanLeaf::True, 5));
+    let tree: MiniTree = construction.clone().to_tree().unwrap();
+    let (output, leaves) = tree.compute_with_leaves(&false);
+    assert!(output);
+    assert_eq!(
+        leaves,
+        (0..5).map(|index| (index, true)).collect::<Vec<_>>()
+    );
+    // The same holds when walking the slots from the top.
+    construction.value = BooleanComparator::Priority;
+    let tree: MiniTree = construction.to_tree().unwrap();
+    let (_, leaves) = tree.compute_with_leaves(&false);
+    assert_eq!(leaves, vec![(4, true)]);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn children_mask_combinators() {
+    let a = ChildrenMask {
+        branch_mask: 0b0011u8,
+        leaf_mask: 0b1100u16,
+    };
+    let b = ChildrenMask {
+        branch_mask: 0b0110,
+        leaf_mask: 0b0011,
+    };
+    assert_eq!(
+        a.union(b),
+        ChildrenMask {
+            branch_mask: 0b0111,
+            leaf_mask: 0b1111,
+        }
+    );
+    let both = a.intersection(b);
+    assert_eq!(both.branch_mask, 0b0010);
+    assert_eq!(both.leaf_mask, 0);
+    assert!(!both.is_empty());
+    assert!(
+        ChildrenMask::<u8, u16> {
+            branch_mask: 0,
+            leaf_mask: 0
+        }
+        .is_empty()
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn build_tree_in_one_call() {
+    let tree: MiniTree = build_tree(BooleanComparator::Or, |root| {
+        root.add_leaf(BooleanLeaf::False);
+        root.add_branch_with(BooleanComparator::And, |branch| {
+            branch.add_leaf(BooleanLeaf::True);
+            branch.add_leaf(BooleanLeaf::InsertedValue);
+        });
+    })
+    .unwrap();
+    let expected: MiniTree = expr_tree!(BooleanComparator::Or {
+        BooleanLeaf::False,
+        BooleanComparator::And { BooleanLeaf::True, BooleanLeaf::InsertedValue },
+    })
+    .to_tree()
+    .unwrap();
+    assert_eq!(tree, expected);
+    let overflow =
+        build_tree::<_, _, MiniTreeBA, MiniTreeLA, u8, u16>(BooleanComparator::Or, |root| {
+            root.extend_leaves(std::iter::repeat_n(BooleanLeaf::True, 17));
+        });
+    assert!(matches!(
+        overflow,
+        Err(ConstructionError::InsufficientLeafCapacity { required: 17, .. })
+    ));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_budgeted() {
+    // Seven nodes: the root, two `And`s and four leaves.
+    let tree: MiniTree = expr_tree!(BooleanComparator::Parity {
+        BooleanComparator::And { BooleanLeaf::InsertedValue, BooleanLeaf::True },
+        BooleanComparator::And { BooleanLeaf::True, BooleanLeaf::True },
+    })
+    .to_tree()
+    .unwrap();
+    assert_eq!(tree.full_pass_node_count(), 7);
+    assert_eq!(tree.compute_budgeted(&true, 7), Ok(tree.compute(&true)));
+    assert_eq!(tree.compute_budgeted(&true, u32::MAX), Ok(false));
+    assert_eq!(tree.compute_budgeted(&true, 6), Err(BudgetExceeded));
+    assert_eq!(tree.compute_budgeted(&true, 0), Err(BudgetExceeded));
+    // The budget is checked before computing, so nodes the first `And` would
+    // skip for `false` still count.
+    assert_eq!(tree.compute_budgeted(&false, 6), Err(BudgetExceeded));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_budgeted_never_truncates_children() {
+    // Both branches panic if they get fewer children than their masks select.
+    let majority: MiniTree = expr_tree!(BooleanComparator::Majority {
+        BooleanLeaf::True,
+        BooleanLeaf::True,
+        BooleanLeaf::False,
+    })
+    .to_tree()
+    .unwrap();
+    let peek: MiniTree = expr_tree!(BooleanComparator::Peek {
+        BooleanComparator::And { BooleanLeaf::True },
+        BooleanLeaf::False,
+    })
+    .to_tree()
+    .unwrap();
+    for budget in 0..4 {
+        assert_eq!(
+            majority.compute_budgeted(&true, budget),
+            Err(BudgetExceeded)
+        );
+        assert_eq!(peek.compute_budgeted(&true, budget), Err(BudgetExceeded));
+    }
+    assert_eq!(majority.full_pass_node_count(), 4);
+    assert_eq!(majority.compute_budgeted(&true, 4), Ok(true));
+    assert_eq!(peek.compute_budgeted(&true, 4), Ok(true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn edit_at_path() {
+    let mut construction = expr_tree!(BooleanComparator::Or {
+        BooleanComparator::And { BooleanLeaf::True },
+        BooleanLeaf::False,
+    });
+    // Children of the inner `And`: nothing yet before its leaf at 0.
+    construction
+        .insert_branch_at(
+            &[0, 0],
+            ConstructableTreeBranch::new(BooleanComparator::Xor),
+        )
+        .unwrap();
+    construction
+        .insert_leaf_at(&[0, 0, 0], BooleanLeaf::InsertedValue)
+        .unwrap();
+    construction
+        .insert_leaf_at(&[0, 2], BooleanLeaf::False)
+        .unwrap();
+    assert_eq!(
+        construction,
+        expr_tree!(BooleanComparator::Or {
+            BooleanComparator::And {
+                BooleanComparator::Xor { BooleanLeaf::InsertedValue },
+                BooleanLeaf::True,
+                BooleanLeaf::False,
+            },
+            BooleanLeaf::False,
+        })
+    );
+    assert_eq!(
+        construction.remove_at(&[0, 1]),
+        Ok(OwnedNode::Leaf(BooleanLeaf::True))
+    );
+    assert_eq!(
+        construction.remove_at(&[0, 0]),
+        Ok(OwnedNode::Branch(
+            ConstructableTreeBranch::new(BooleanComparator::Xor)
+                .with_leaf(BooleanLeaf::InsertedValue)
+        ))
+    );
+    assert_eq!(construction.remove_at(&[]), Err(PathError::Empty));
+    assert_eq!(
+        construction.remove_at(&[1, 0]),
+        Err(PathError::OutOfRange { depth: 0 })
+    );
+    assert_eq!(
+        construction.remove_at(&[0, 1]),
+        Err(PathError::OutOfRange { depth: 1 })
+    );
+    // A leaf cannot go before a sub-branch, nor a branch after a leaf.
+    assert_eq!(
+        construction.insert_leaf_at(&[0], BooleanLeaf::True),
+        Err(PathError::OutOfRange { depth: 0 })
+    );
+    assert_eq!(
+        construction.insert_branch_at(&[2], ConstructableTreeBranch::new(BooleanComparator::And)),
+        Err(PathError::OutOfRange { depth: 0 })
+    );
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_first_success() {
+    let tree = |slot| -> MiniTree {
+        expr_tree!(BooleanComparator::FirstTrueAt(slot) {
+            BooleanComparator::And { BooleanLeaf::InsertedValue, BooleanLeaf::InsertedValue },
+            BooleanLeaf::False,
+            BooleanLeaf::True,
+            BooleanLeaf::Unreachable,
+        })
+        .to_tree()
+        .unwrap()
+    };
+    // The leaves of the sub-branch take slots 0 and 1, so the `True` leaf is
+    // the third child but sits in slot 3, and the `Unreachable` leaf after it
+    // is never computed.
+    assert!(tree(ChildSlot::Leaf(3)).compute(&false));
+    assert!(!tree(ChildSlot::Leaf(2)).compute(&false));
+    assert!(!tree(ChildSlot::Leaf(3)).compute(&true));
+    assert!(tree(ChildSlot::Branch(0)).compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_first_success_below_root() {
+    let tree = |slot| -> MiniTree {
+        expr_tree!(BooleanComparator::And {
+            BooleanLeaf::True,
+            BooleanComparator::FirstTrueAt(slot) {
+                BooleanComparator::Or { BooleanLeaf::False, BooleanLeaf::False },
+                BooleanComparator::And { BooleanLeaf::InsertedValue },
+                BooleanLeaf::False,
+                BooleanLeaf::True,
+            },
+        })
+        .to_tree()
+        .unwrap()
+    };
+    // The sub-branches take branch slots 0 and 1 and leaf slots 0 to 2, so
+    // the `True` leaf, the second leaf of `FirstTrueAt`, sits in slot 4.
+    assert!(tree(ChildSlot::Leaf(4)).compute(&false));
+    assert!(!tree(ChildSlot::Leaf(1)).compute(&false));
+    assert!(tree(ChildSlot::Branch(1)).compute(&true));
+    assert!(!tree(ChildSlot::Branch(0)).compute(&true));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn count_true() {
+    let tree = |k| -> MiniTree {
+        expr_tree!(BooleanComparator::AtLeast(k) {
+            BooleanComparator::Or { BooleanLeaf::InsertedValue, BooleanLeaf::False },
+            BooleanLeaf::True,
+            BooleanLeaf::False,
+            BooleanLeaf::InsertedValue,
+        })
+        .to_tree()
+        .unwrap()
+    };
+    assert!(tree(3).compute(&true));
+    assert!(!tree(4).compute(&true));
+    assert!(tree(1).compute(&false));
+    assert!(!tree(2).compute(&false));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_with_fired_mask() {
+    let tree: MiniTree = expr_tree!(BooleanComparator::Xor {
+        BooleanComparator::And { BooleanLeaf::InsertedValue, BooleanLeaf::True },
+        BooleanLeaf::False,
+        BooleanLeaf::True,
+    })
+    .to_tree()
+    .unwrap();
+    assert_eq!(tree.compute_with_fired_mask(&true), (false, 0b1011));
+    // `And` stops at its false first leaf, so its second leaf never fires.
+    assert_eq!(tree.compute_with_fired_mask(&false), (true, 0b1000));
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn into_leaves() {
+    let construction = expr_tree!(BooleanComparator::Or {
+        BooleanLeaf::False,
+        BooleanComparator::And { BooleanLeaf::True, BooleanLeaf::InsertedValue },
+        BooleanComparator::And { BooleanLeaf::Unreachable },
+    });
+    let tree: MiniTree = construction.clone().to_tree().unwrap();
+    let leaves = construction.into_leaves();
+    assert_eq!(
+        leaves,
+        [
+            BooleanLeaf::True,
+            BooleanLeaf::InsertedValue,
+            BooleanLeaf::Unreachable,
+            BooleanLeaf::False,
+        ]
+    );
+    assert!(tree.iter_leaves().eq(&leaves));
 }
@@ -0,0 +1,25 @@
+use tiny_expr_tree::ChildrenMask;
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn cast_masks_from_bytes() {
+    // Masks as they would be laid out in a flash region.
+    static FLASH: [u16; 4] = [0b0011, 0b0100, 0, 0b1000];
+    let masks: &[ChildrenMask<u16, u16>] = bytemuck::cast_slice(&FLASH);
+    assert_eq!(
+        masks,
+        [
+            ChildrenMask {
+                branch_mask: 0b0011,
+                leaf_mask: 0b0100,
+            },
+            ChildrenMask {
+                branch_mask: 0,
+                leaf_mask: 0b1000,
+            },
+        ]
+    );
+    assert_eq!(bytemuck::cast_slice::<_, u16>(masks), FLASH);
+    let zeroed: ChildrenMask<u8, u32> = bytemuck::Zeroable::zeroed();
+    assert!(zeroed.is_empty());
+}
@@ -0,0 +1,154 @@
+use std::cell::Cell;
+
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, IncrementalCache,
+    LeafNode, MaskTrackedArrayU8, alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+/// Counts how many nodes were computed.
+struct Counter {
+    scale: u32,
+    computed: Cell<u32>,
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Value(u32);
+impl ComputableLeaf for Value {
+    type LeafContext = Counter;
+    type LeafOutput = u32;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        context.computed.set(context.computed.get() + 1);
+        self.0 * context.scale
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Sum;
+impl<BA, LA, BM, LM> ComputableBranch<Value, BA, LA, BM, LM> for Sum
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Value>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = Counter;
+    type BranchOutput = u32;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Value>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Value, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        context.computed.set(context.computed.get() + 1);
+        controls.compute_all_both(context).sum()
+    }
+}
+make_tree_aliases!(SumTree, Sum, Value, u8, u8);
+type SumCache = IncrementalCache<u32, u32, MaskTrackedArrayU8<u32>, MaskTrackedArrayU8<u32>>;
+
+fn counter(scale: u32) -> Counter {
+    Counter {
+        scale,
+        computed: Cell::new(0),
+    }
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_incremental() {
+    let tree: SumTree = ConstructableTreeBranch::new(Sum)
+        .with_leaf(Value(1))
+        .with_branch(
+            ConstructableTreeBranch::new(Sum)
+                .with_leaf(Value(2))
+                .with_leaf(Value(3)),
+        )
+        .to_tree()
+        .unwrap();
+    let mut cache = SumCache::new();
+    let context = counter(1);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 6);
+    assert_eq!(context.computed.get(), 5);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 6);
+    assert_eq!(context.computed.get(), 5);
+
+    let context = counter(10);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 6);
+    cache.clear();
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 60);
+    assert_eq!(tree.compute(&context), 60);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn mark_leaf_dirty() {
+    let mut tree: SumTree = ConstructableTreeBranch::new(Sum)
+        .with_leaf(Value(1))
+        .with_branch(
+            ConstructableTreeBranch::new(Sum)
+                .with_leaf(Value(2))
+                .with_branch(ConstructableTreeBranch::new(Sum).with_leaf(Value(3))),
+        )
+        .with_branch(ConstructableTreeBranch::new(Sum).with_leaf(Value(4)))
+        .to_tree()
+        .unwrap();
+    let mut cache = SumCache::new();
+    let context = counter(1);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 10);
+    assert_eq!(context.computed.get(), 8);
+    let context = counter(1);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 10);
+    assert_eq!(context.computed.get(), 0);
+    // Slot 0 holds the deepest leaf, `Value(3)`.
+    *tree.leaf_mut(0).unwrap() = Value(30);
+    assert!(tree.mark_leaf_dirty(&mut cache, 0));
+    let context = counter(1);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 37);
+    // The leaf and the three branches above it.
+    assert_eq!(context.computed.get(), 4);
+    assert_eq!(tree.compute(&counter(1)), 37);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn mark_shared_leaf_dirty() {
+    let pair = || {
+        ConstructableTreeBranch::new(Sum)
+            .with_leaf(Value(1))
+            .with_leaf(Value(2))
+    };
+    let mut tree: SumTree = ConstructableTreeBranch::new(Sum)
+        .with_branch(pair())
+        .with_branch(
+            ConstructableTreeBranch::new(Sum)
+                .with_leaf(Value(5))
+                .with_branch(pair()),
+        )
+        .to_tree_dedup()
+        .unwrap();
+    assert_eq!(tree.leaf_count(), 3);
+    let mut cache = SumCache::new();
+    assert_eq!(tree.compute_incremental(&counter(1), &mut cache), 11);
+    // Slot 0 holds `Value(1)`, which both copies of the pair share.
+    *tree.leaf_mut(0).unwrap() = Value(10);
+    assert!(tree.mark_leaf_dirty(&mut cache, 0));
+    let context = counter(1);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 29);
+    assert_eq!(context.computed.get(), 4);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn mark_missing_leaf_dirty() {
+    let tree: SumTree = ConstructableTreeBranch::new(Sum)
+        .with_leaf(Value(1))
+        .with_leaf(Value(2))
+        .to_tree()
+        .unwrap();
+    let mut cache = SumCache::new();
+    assert_eq!(tree.compute_incremental(&counter(1), &mut cache), 3);
+    // Slot 2 is empty, and slot 9 is past the end of the `u8` leaf mask.
+    assert!(!tree.mark_leaf_dirty(&mut cache, 2));
+    assert!(!tree.mark_leaf_dirty(&mut cache, 9));
+    assert!(!tree.mark_leaf_dirty(&mut cache, u32::MAX));
+    let context = counter(1);
+    assert_eq!(tree.compute_incremental(&context, &mut cache), 3);
+    assert_eq!(context.computed.get(), 0);
+}
@@ -0,0 +1,100 @@
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranchMut, ComputableLeafMut, LeafNode,
+    alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+/// Records the id of every sensor that was read.
+#[derive(Debug, Default)]
+struct Log {
+    read: Vec<u8>,
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Sensor {
+    id: u8,
+    active: bool,
+}
+impl ComputableLeafMut for Sensor {
+    type LeafContext = Log;
+    type LeafOutput = bool;
+    fn compute_mut(&self, context: &mut Self::LeafContext) -> Self::LeafOutput {
+        context.read.push(self.id);
+        self.active
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Gate {
+    All,
+    Any,
+    /// Only reads its leaves, and counts how many were active.
+    CountLeaves,
+}
+impl<BA, LA, BM, LM> ComputableBranchMut<Sensor, BA, LA, BM, LM> for Gate
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Sensor>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = Log;
+    type BranchOutput = bool;
+    fn compute_mut<'a>(
+        &self,
+        context: &mut Self::BranchContext,
+        controls: BranchControls<'a, Self, Sensor, BA, LA, BM, LM>,
+    ) -> Self::BranchOutput {
+        match self {
+            Self::All => controls.compute_all_both_mut(context).all(|v| v),
+            Self::Any => controls.compute_all_both_mut(context).any(|v| v),
+            Self::CountLeaves => {
+                let active = controls
+                    .compute_all_leaves_mut(context)
+                    .filter(|v| *v)
+                    .count();
+                active > 0 && controls.compute_all_branches_mut(context).all(|v| v)
+            }
+        }
+    }
+}
+make_tree_aliases!(GateTree, Gate, Sensor, u8, u8);
+
+fn sensor(id: u8, active: bool) -> Sensor {
+    Sensor { id, active }
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_mut_logs_in_order() {
+    let tree: GateTree = ConstructableTreeBranch::new(Gate::All)
+        .with_leaf(sensor(0, true))
+        .with_branch(
+            ConstructableTreeBranch::new(Gate::Any)
+                .with_leaf(sensor(1, false))
+                .with_leaf(sensor(2, true))
+                .with_leaf(sensor(3, true)),
+        )
+        .with_branch(
+            ConstructableTreeBranch::new(Gate::CountLeaves)
+                .with_leaf(sensor(4, true))
+                .with_branch(ConstructableTreeBranch::new(Gate::Any).with_leaf(sensor(5, true))),
+        )
+        .to_tree()
+        .unwrap();
+    let mut log = Log::default();
+    assert!(tree.compute_mut(&mut log));
+    // Sub-branches come before leaves, and `Any` stops at the first active
+    // sensor.
+    assert_eq!(log.read, vec![1, 2, 4, 5, 0]);
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn compute_mut_stops_early() {
+    let tree: GateTree = ConstructableTreeBranch::new(Gate::All)
+        .with_leaf(sensor(0, false))
+        .with_leaf(sensor(1, true))
+        .to_tree()
+        .unwrap();
+    let mut log = Log::default();
+    assert!(!tree.compute_mut(&mut log));
+    assert_eq!(log.read, vec![0]);
+}
@@ -0,0 +1,309 @@
+// Only `tiny_expr_tree` is imported, to check that its re-exports are enough
+// to implement the traits and use the aliases macro.
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableBranchMut, ComputableLeaf,
+    ComputableLeafMut, ComputeHooks, LeafNode, Mask, MaskTrackedArray, TryComputableBranch,
+    TryComputableLeaf, alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+/// A window of `len` readings starting at `start` into all sensor readings.
+#[derive(Debug, Clone, Copy)]
+struct Readings {
+    values: [i16; 8],
+    start: usize,
+    len: usize,
+}
+impl Readings {
+    fn new(values: [i16; 8]) -> Self {
+        Self {
+            values,
+            start: 0,
+            len: values.len(),
+        }
+    }
+    fn get(&self, index: usize) -> Option<i16> {
+        (index < self.len).then(|| self.values[self.start + index])
+    }
+}
+/// Whether the reading at an index into the current window is positive.
+#[derive(Debug, Clone, PartialEq)]
+struct Positive(usize);
+impl ComputableLeaf for Positive {
+    type LeafContext = Readings;
+    type LeafOutput = bool;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        context.get(self.0).is_some_and(|value| value > 0)
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Gate {
+    All,
+    /// Narrows the window to `len` readings starting at `start` for its
+    /// children.
+    Window {
+        start: usize,
+        len: usize,
+    },
+}
+impl<BA, LA, BM, LM> ComputableBranch<Positive, BA, LA, BM, LM> for Gate
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Positive>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = Readings;
+    type BranchOutput = bool;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Positive>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Positive, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        match self {
+            Self::All => controls.compute_all(context),
+            Self::Window { start, len } => {
+                let narrowed = Readings {
+                    start: context.start + start,
+                    len: *len,
+                    ..*context
+                };
+                controls.compute_all(&narrowed)
+            }
+        }
+    }
+}
+make_tree_aliases!(GateTree, Gate, Positive, u8, u8);
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn branches_narrow_context() {
+    // Both windows check their own first two readings with the same leaves.
+    let window = |start| {
+        ConstructableTreeBranch::new(Gate::Window { start, len: 2 })
+            .with_leaf(Positive(0))
+            .with_leaf(Positive(1))
+    };
+    let tree: GateTree = ConstructableTreeBranch::new(Gate::All)
+        .with_branch(window(0))
+        .with_branch(window(4))
+        .to_tree()
+        .unwrap();
+    assert!(tree.compute(&Readings::new([1, 2, -1, -1, 3, 4, -1, -1])));
+    assert!(!tree.compute(&Readings::new([1, 2, -1, -1, 3, -4, -1, -1])));
+}
+
+/// Whether the current reading is above a threshold. Unlike [`Positive`], the
+/// leaf context is a single reading rather than the branch context.
+#[derive(Debug, Clone, PartialEq)]
+struct Above(i16);
+impl ComputableLeaf for Above {
+    type LeafContext = i16;
+    type LeafOutput = bool;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        *context > self.0
+    }
+}
+/// True when at least as many leaves as the branch context are true.
+#[derive(Debug, Clone, PartialEq)]
+struct AtLeast;
+impl<BA, LA, BM, LM> ComputableBranch<Above, BA, LA, BM, LM> for AtLeast
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Above>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = usize;
+    type BranchOutput = bool;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Above>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Above, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        let reading = controls
+            .leaf_context()
+            .expect("computed with compute_split");
+        let passed = controls
+            .compute_all_branches(context)
+            .chain(controls.compute_all_leaves(reading))
+            .filter(|v| *v)
+            .count();
+        passed >= *context
+    }
+}
+make_tree_aliases!(AtLeastTree, AtLeast, Above, u8, u8);
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn split_contexts() {
+    let tree: AtLeastTree = ConstructableTreeBranch::new(AtLeast)
+        .with_leaf(Above(0))
+        .with_branch(
+            ConstructableTreeBranch::new(AtLeast)
+                .with_leaf(Above(10))
+                .with_leaf(Above(20)),
+        )
+        .to_tree()
+        .unwrap();
+    // The reading reaches the sub-branch's leaves as well.
+    assert!(tree.compute_split(&2, &25));
+    assert!(!tree.compute_split(&2, &15));
+    assert!(tree.compute_split(&1, &15));
+}
+
+/// Whether the first reading of the slice it is given is positive.
+#[derive(Debug, Clone, PartialEq)]
+struct FirstPositive;
+impl ComputableLeaf for FirstPositive {
+    type LeafContext = [i16];
+    type LeafOutput = bool;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        context.first().is_some_and(|value| *value > 0)
+    }
+}
+/// Passes its children a sub-slice without the first few readings.
+#[derive(Debug, Clone, PartialEq)]
+struct Skip(usize);
+impl<BA, LA, BM, LM> ComputableBranch<FirstPositive, BA, LA, BM, LM> for Skip
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<FirstPositive>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = [i16];
+    type BranchOutput = bool;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, FirstPositive>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, FirstPositive, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        controls.compute_all(context.get(self.0..).unwrap_or_default())
+    }
+}
+make_tree_aliases!(SkipTree, Skip, FirstPositive, u8, u8);
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn slice_context_outlived_by_tree() {
+    let tree: SkipTree = ConstructableTreeBranch::new(Skip(0))
+        .with_leaf(FirstPositive)
+        .with_branch(ConstructableTreeBranch::new(Skip(2)).with_leaf(FirstPositive))
+        .to_tree()
+        .unwrap();
+    let mut results = Vec::new();
+    for frame in 0..3i16 {
+        // Each frame's readings are dropped before the next one while the
+        // tree keeps being reused.
+        let readings = vec![1, -1, frame - 1];
+        results.push(tree.compute(&readings[..]));
+        results.push(tree.compute_split(&readings, &readings[2..]));
+    }
+    assert_eq!(results, [false, false, false, false, true, true]);
+}
+
+/// Whether the first reading of the slice it is given is positive, failing
+/// when the slice is empty.
+#[derive(Debug, Clone, PartialEq)]
+struct TryFirstPositive;
+impl TryComputableLeaf for TryFirstPositive {
+    type LeafContext = [i16];
+    type LeafOutput = bool;
+    type Error = ();
+    fn try_compute(&self, context: &Self::LeafContext) -> Result<Self::LeafOutput, Self::Error> {
+        context.first().map(|value| *value > 0).ok_or(())
+    }
+}
+/// Like [`Skip`], for fallible trees.
+#[derive(Debug, Clone, PartialEq)]
+struct TrySkip(usize);
+impl<BA, LA, BM, LM> TryComputableBranch<TryFirstPositive, BA, LA, BM, LM> for TrySkip
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<TryFirstPositive>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = [i16];
+    type BranchOutput = bool;
+    type Error = ();
+    fn try_compute<'a>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, TryFirstPositive, BA, LA, BM, LM>,
+    ) -> Result<Self::BranchOutput, Self::Error> {
+        let context = context.get(self.0..).unwrap_or_default();
+        controls
+            .try_compute_all_both(context)
+            .try_fold(true, |all, value| Ok(all && value?))
+    }
+}
+make_tree_aliases!(TrySkipTree, TrySkip, TryFirstPositive, u8, u8);
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn slice_context_try_compute() {
+    let tree: TrySkipTree = ConstructableTreeBranch::new(TrySkip(0))
+        .with_leaf(TryFirstPositive)
+        .with_branch(ConstructableTreeBranch::new(TrySkip(2)).with_leaf(TryFirstPositive))
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree.try_compute(&[1, -1, 1][..]), Ok(true));
+    assert_eq!(tree.try_compute(&[1, -1, -1][..]), Ok(false));
+    assert_eq!(tree.try_compute(&[1, -1][..]), Err(()));
+}
+
+/// Takes the first reading of the slice it is given, leaving zero behind,
+/// and returns whether it was positive.
+#[derive(Debug, Clone, PartialEq)]
+struct TakeFirst;
+impl ComputableLeafMut for TakeFirst {
+    type LeafContext = [i16];
+    type LeafOutput = bool;
+    fn compute_mut(&self, context: &mut Self::LeafContext) -> Self::LeafOutput {
+        context
+            .first_mut()
+            .is_some_and(|value| core::mem::take(value) > 0)
+    }
+}
+/// Like [`Skip`], handing its children a mutable sub-slice.
+#[derive(Debug, Clone, PartialEq)]
+struct SkipMut(usize);
+impl<BA, LA, BM, LM> ComputableBranchMut<TakeFirst, BA, LA, BM, LM> for SkipMut
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<TakeFirst>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = [i16];
+    type BranchOutput = usize;
+    fn compute_mut<'a>(
+        &self,
+        context: &mut Self::BranchContext,
+        controls: BranchControls<'a, Self, TakeFirst, BA, LA, BM, LM>,
+    ) -> Self::BranchOutput {
+        let context = context.get_mut(self.0..).unwrap_or_default();
+        let leaves = controls
+            .compute_all_leaves_mut(context)
+            .filter(|v| *v)
+            .count();
+        leaves + controls.compute_all_branches_mut(context).sum::<usize>()
+    }
+}
+make_tree_aliases!(SkipMutTree, SkipMut, TakeFirst, u8, u8);
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn slice_context_compute_mut() {
+    let tree: SkipMutTree = ConstructableTreeBranch::new(SkipMut(0))
+        .with_leaf(TakeFirst)
+        .with_leaf(TakeFirst)
+        .with_branch(ConstructableTreeBranch::new(SkipMut(2)).with_leaf(TakeFirst))
+        .to_tree()
+        .unwrap();
+    let mut readings = vec![1, -1, 3];
+    // The second leaf sees the zero the first one left behind.
+    assert_eq!(tree.compute_mut(&mut readings[..]), 2);
+    assert_eq!(readings, [0, -1, 0]);
+    assert_eq!(tree.compute_mut(&mut readings[..]), 0);
+}
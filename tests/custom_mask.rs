@@ -0,0 +1,320 @@
+use std::marker::PhantomData;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub};
+
+use mask_tracked_array::{Mask, MaskTrackedArray, MaskTrackedArrayU16};
+use num_traits::{
+    Bounded, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, ConstOne, ConstZero, Euclid, Num,
+    NumCast, One, PrimInt, Saturating, ToPrimitive, Zero,
+};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, LeafNode,
+    TinyExprTree, alloc_gen::ConstructableTreeBranch,
+};
+
+/// A `u16` mask that borrows for `'a`, like a mask tied to a register block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct BorrowedMask<'a>(u16, PhantomData<&'a ()>);
+
+impl BorrowedMask<'_> {
+    const fn new(bits: u16) -> Self {
+        Self(bits, PhantomData)
+    }
+}
+
+macro_rules! forward_binary_ops {
+    ($($op:ident $method:ident),*) => {
+        $(impl $op for BorrowedMask<'_> {
+            type Output = Self;
+            fn $method(self, rhs: Self) -> Self {
+                Self::new($op::$method(self.0, rhs.0))
+            }
+        })*
+    };
+}
+forward_binary_ops!(
+    Add add, Sub sub, Mul mul, Div div, Rem rem, BitAnd bitand, BitOr bitor, BitXor bitxor
+);
+
+macro_rules! forward_checked_ops {
+    ($($op:ident $method:ident),*) => {
+        $(impl $op for BorrowedMask<'_> {
+            fn $method(&self, rhs: &Self) -> Option<Self> {
+                self.0.$method(rhs.0).map(Self::new)
+            }
+        })*
+    };
+}
+forward_checked_ops!(
+    CheckedAdd checked_add, CheckedSub checked_sub, CheckedMul checked_mul,
+    CheckedDiv checked_div
+);
+
+impl Not for BorrowedMask<'_> {
+    type Output = Self;
+    fn not(self) -> Self {
+        Self::new(!self.0)
+    }
+}
+impl Shl<usize> for BorrowedMask<'_> {
+    type Output = Self;
+    fn shl(self, rhs: usize) -> Self {
+        Self::new(self.0 << rhs)
+    }
+}
+impl Shr<usize> for BorrowedMask<'_> {
+    type Output = Self;
+    fn shr(self, rhs: usize) -> Self {
+        Self::new(self.0 >> rhs)
+    }
+}
+impl Zero for BorrowedMask<'_> {
+    fn zero() -> Self {
+        Self::ZERO
+    }
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+impl One for BorrowedMask<'_> {
+    fn one() -> Self {
+        Self::ONE
+    }
+}
+impl ConstZero for BorrowedMask<'_> {
+    const ZERO: Self = Self::new(0);
+}
+impl ConstOne for BorrowedMask<'_> {
+    const ONE: Self = Self::new(1);
+}
+impl Num for BorrowedMask<'_> {
+    type FromStrRadixErr = <u16 as Num>::FromStrRadixErr;
+    fn from_str_radix(str: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        u16::from_str_radix(str, radix).map(Self::new)
+    }
+}
+impl ToPrimitive for BorrowedMask<'_> {
+    fn to_i64(&self) -> Option<i64> {
+        self.0.to_i64()
+    }
+    fn to_u64(&self) -> Option<u64> {
+        self.0.to_u64()
+    }
+}
+impl NumCast for BorrowedMask<'_> {
+    fn from<T: ToPrimitive>(n: T) -> Option<Self> {
+        n.to_u16().map(Self::new)
+    }
+}
+impl Bounded for BorrowedMask<'_> {
+    fn min_value() -> Self {
+        Self::new(u16::MIN)
+    }
+    fn max_value() -> Self {
+        Self::new(u16::MAX)
+    }
+}
+impl Saturating for BorrowedMask<'_> {
+    fn saturating_add(self, rhs: Self) -> Self {
+        Self::new(self.0.saturating_add(rhs.0))
+    }
+    fn saturating_sub(self, rhs: Self) -> Self {
+        Self::new(self.0.saturating_sub(rhs.0))
+    }
+}
+impl Euclid for BorrowedMask<'_> {
+    fn div_euclid(&self, rhs: &Self) -> Self {
+        Self::new(self.0.div_euclid(rhs.0))
+    }
+    fn rem_euclid(&self, rhs: &Self) -> Self {
+        Self::new(self.0.rem_euclid(rhs.0))
+    }
+}
+impl PrimInt for BorrowedMask<'_> {
+    fn count_ones(self) -> u32 {
+        self.0.count_ones()
+    }
+    fn count_zeros(self) -> u32 {
+        self.0.count_zeros()
+    }
+    fn leading_zeros(self) -> u32 {
+        self.0.leading_zeros()
+    }
+    fn trailing_zeros(self) -> u32 {
+        self.0.trailing_zeros()
+    }
+    fn rotate_left(self, n: u32) -> Self {
+        Self::new(self.0.rotate_left(n))
+    }
+    fn rotate_right(self, n: u32) -> Self {
+        Self::new(self.0.rotate_right(n))
+    }
+    fn signed_shl(self, n: u32) -> Self {
+        Self::new(PrimInt::signed_shl(self.0, n))
+    }
+    fn signed_shr(self, n: u32) -> Self {
+        Self::new(PrimInt::signed_shr(self.0, n))
+    }
+    fn unsigned_shl(self, n: u32) -> Self {
+        Self::new(PrimInt::unsigned_shl(self.0, n))
+    }
+    fn unsigned_shr(self, n: u32) -> Self {
+        Self::new(PrimInt::unsigned_shr(self.0, n))
+    }
+    fn swap_bytes(self) -> Self {
+        Self::new(self.0.swap_bytes())
+    }
+    fn from_be(x: Self) -> Self {
+        Self::new(u16::from_be(x.0))
+    }
+    fn from_le(x: Self) -> Self {
+        Self::new(u16::from_le(x.0))
+    }
+    fn to_be(self) -> Self {
+        Self::new(self.0.to_be())
+    }
+    fn to_le(self) -> Self {
+        Self::new(self.0.to_le())
+    }
+    fn pow(self, exp: u32) -> Self {
+        Self::new(self.0.pow(exp))
+    }
+}
+impl Mask for BorrowedMask<'_> {
+    const ALL_SELECTED: Self = Self::new(u16::MAX);
+    const NONE_SELECTED: Self = Self::new(0);
+    const ONE_SELECTED: Self = Self::new(1);
+    const MAX_SELECTIONS: u32 = u16::BITS;
+    fn mask_to_indices(self) -> impl Iterator<Item = usize> {
+        self.0.mask_to_indices()
+    }
+}
+
+/// A [`MaskTrackedArrayU16`] whose mask is a [`BorrowedMask`].
+#[derive(Debug, PartialEq)]
+struct BorrowedArray<'a, T>(MaskTrackedArrayU16<T>, PhantomData<&'a ()>);
+
+impl<T> Default for BorrowedArray<'_, T> {
+    fn default() -> Self {
+        Self(MaskTrackedArrayU16::new(), PhantomData)
+    }
+}
+impl<T> FromIterator<T> for BorrowedArray<'_, T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self(iter.into_iter().collect(), PhantomData)
+    }
+}
+impl<T> FromIterator<(usize, T)> for BorrowedArray<'_, T> {
+    fn from_iter<I: IntoIterator<Item = (usize, T)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect(), PhantomData)
+    }
+}
+impl<'a, T> MaskTrackedArray<T> for BorrowedArray<'a, T> {
+    type MaskType = BorrowedMask<'a>;
+    fn contains_item_at(&self, index: usize) -> bool {
+        self.0.contains_item_at(index)
+    }
+    fn len(&self) -> u32 {
+        self.0.len()
+    }
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+    unsafe fn get_unchecked_ref(&self, index: usize) -> &T {
+        unsafe { self.0.get_unchecked_ref(index) }
+    }
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_unchecked_mut(&self, index: usize) -> &mut T {
+        unsafe { self.0.get_unchecked_mut(index) }
+    }
+    unsafe fn insert_unchecked(&self, index: usize, value: T) {
+        unsafe { self.0.insert_unchecked(index, value) }
+    }
+    unsafe fn remove_unchecked(&self, index: usize) -> T {
+        unsafe { self.0.remove_unchecked(index) }
+    }
+    fn iter_filled_indices(&self) -> impl Iterator<Item = usize> {
+        self.0.iter_filled_indices()
+    }
+    fn iter_filled_indices_mask(&self, mask: Self::MaskType) -> impl Iterator<Item = usize> {
+        self.0.iter_filled_indices_mask(mask.0)
+    }
+    fn iter_empty_indices(&self) -> impl Iterator<Item = usize> {
+        self.0.iter_empty_indices()
+    }
+    fn mask(&self) -> Self::MaskType {
+        BorrowedMask::new(self.0.mask())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Above(u32);
+impl ComputableLeaf for Above {
+    type LeafContext = u32;
+    type LeafOutput = bool;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        *context > self.0
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Gate {
+    All,
+    Any,
+}
+impl<BA, LA, BM, LM> ComputableBranch<Above, BA, LA, BM, LM> for Gate
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Above>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = u32;
+    type BranchOutput = bool;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Above>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Above, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        let mut children = controls.compute_all_both(context);
+        match self {
+            Self::All => children.all(|v| v),
+            Self::Any => children.any(|v| v),
+        }
+    }
+}
+type BorrowedTree<'a> = TinyExprTree<
+    Gate,
+    Above,
+    BorrowedArray<'a, BranchNode<Gate, BorrowedMask<'a>, BorrowedMask<'a>>>,
+    BorrowedArray<'a, LeafNode<Above>>,
+    BorrowedMask<'a>,
+    BorrowedMask<'a>,
+>;
+
+/// Build a tree whose masks borrow for as long as `_registers`.
+fn build(_registers: &[u16]) -> BorrowedTree<'_> {
+    ConstructableTreeBranch::new(Gate::All)
+        .with_leaf(Above(1))
+        .with_branch(
+            ConstructableTreeBranch::new(Gate::Any)
+                .with_leaf(Above(10))
+                .with_leaf(Above(3)),
+        )
+        .to_tree()
+        .unwrap()
+}
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn custom_mask_with_lifetime() {
+    let registers = vec![0u16; 4];
+    let tree = build(&registers);
+    assert_eq!(tree.branch_count(), 2);
+    assert_eq!(tree.leaf_count(), 3);
+    assert!(!tree.compute(&1));
+    assert!(!tree.compute(&3));
+    assert!(tree.compute(&4));
+    let copy = tree.clone();
+    assert_eq!(copy, tree);
+    drop(tree);
+    assert!(copy.compute(&11));
+}
@@ -0,0 +1,77 @@
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, LeafNode, TryComputableBranch, TryComputableLeaf,
+    alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+#[derive(Debug, Clone, PartialEq)]
+enum CheckedLeaf {
+    Constant(i32),
+    /// Divide the context by this value.
+    DivideBy(i32),
+}
+#[derive(Debug, Clone, PartialEq)]
+enum ComputeError {
+    DivideByZero,
+    Overflow,
+}
+impl TryComputableLeaf for CheckedLeaf {
+    type LeafContext = i32;
+    type LeafOutput = i32;
+    type Error = ComputeError;
+    fn try_compute(&self, context: &Self::LeafContext) -> Result<Self::LeafOutput, Self::Error> {
+        match self {
+            Self::Constant(value) => Ok(*value),
+            Self::DivideBy(divisor) => context
+                .checked_div(*divisor)
+                .ok_or(ComputeError::DivideByZero),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+struct CheckedSum;
+impl<BA, LA, BM, LM> TryComputableBranch<CheckedLeaf, BA, LA, BM, LM> for CheckedSum
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<CheckedLeaf>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = i32;
+    type BranchOutput = i32;
+    type Error = ComputeError;
+    fn try_compute<'a>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, CheckedLeaf, BA, LA, BM, LM>,
+    ) -> Result<Self::BranchOutput, Self::Error> {
+        let mut sum: i32 = 0;
+        for value in controls.try_compute_all_both(context) {
+            sum = sum.checked_add(value?).ok_or(ComputeError::Overflow)?;
+        }
+        Ok(sum)
+    }
+}
+make_tree_aliases!(pub CheckedTree, CheckedSum, CheckedLeaf, u8, u8);
+
+#[cfg(feature = "alloc-gen")]
+#[test]
+fn try_compute() {
+    let tree: CheckedTree = ConstructableTreeBranch::new(CheckedSum)
+        .with_leaf(CheckedLeaf::Constant(1))
+        .with_branch(ConstructableTreeBranch::new(CheckedSum).with_leaf(CheckedLeaf::DivideBy(2)))
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree.try_compute(&10), Ok(6));
+    let tree: CheckedTree = ConstructableTreeBranch::new(CheckedSum)
+        .with_leaf(CheckedLeaf::Constant(i32::MAX))
+        .with_branch(ConstructableTreeBranch::new(CheckedSum).with_leaf(CheckedLeaf::DivideBy(0)))
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree.try_compute(&10), Err(ComputeError::DivideByZero));
+    let tree: CheckedTree = ConstructableTreeBranch::new(CheckedSum)
+        .with_leaf(CheckedLeaf::Constant(i32::MAX))
+        .with_leaf(CheckedLeaf::DivideBy(1))
+        .to_tree()
+        .unwrap();
+    assert_eq!(tree.try_compute(&10), Err(ComputeError::Overflow));
+}
@@ -0,0 +1,79 @@
+use std::cell::Cell;
+
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, LeafNode,
+    alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+/// Counts how many leaves were computed.
+struct Counter {
+    computed: Cell<u32>,
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Value(u32);
+impl ComputableLeaf for Value {
+    type LeafContext = Counter;
+    type LeafOutput = Option<u32>;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        context.computed.set(context.computed.get() + 1);
+        Some(self.0)
+    }
+}
+/// Median of the children, or `None` if there are more than four.
+#[derive(Debug, Clone, PartialEq)]
+struct Median;
+impl<BA, LA, BM, LM> ComputableBranch<Value, BA, LA, BM, LM> for Median
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Value>, MaskType = LM>,
+    BM: Mask,
+    LM: Mask,
+{
+    type BranchContext = Counter;
+    type BranchOutput = Option<u32>;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Value>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Value, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        let mut outputs = controls.collect_children::<4>(context).ok()?;
+        outputs.sort_unstable();
+        *outputs.get(outputs.len() / 2)?
+    }
+}
+make_tree_aliases!(MedianTree, Median, Value, u8, u8);
+
+#[cfg(feature = "heapless")]
+#[test]
+fn collect_children() {
+    let tree: MedianTree = ConstructableTreeBranch::new(Median)
+        .with_leaf(Value(7))
+        .with_leaf(Value(1))
+        .with_branch(
+            ConstructableTreeBranch::new(Median)
+                .with_leaf(Value(9))
+                .with_leaf(Value(4))
+                .with_leaf(Value(5)),
+        )
+        .to_tree()
+        .unwrap();
+    let context = Counter {
+        computed: Cell::new(0),
+    };
+    assert_eq!(tree.compute(&context), Some(5));
+    assert_eq!(context.computed.get(), 5);
+}
+
+#[cfg(feature = "heapless")]
+#[test]
+fn collect_children_over_capacity() {
+    let mut construction = ConstructableTreeBranch::new(Median);
+    construction.extend_leaves((0..5).map(Value));
+    let tree: MedianTree = construction.to_tree().unwrap();
+    let context = Counter {
+        computed: Cell::new(0),
+    };
+    assert_eq!(tree.compute(&context), None);
+    // Nothing is computed once the children are known not to fit.
+    assert_eq!(context.computed.get(), 0);
+}
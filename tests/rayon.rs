@@ -0,0 +1,135 @@
+use mask_tracked_array::{Mask, MaskTrackedArray};
+use rayon::iter::ParallelIterator;
+use tiny_expr_tree::{
+    BranchControls, BranchNode, ComputableBranch, ComputableLeaf, ComputeHooks, LeafNode,
+    alloc_gen::ConstructableTreeBranch, make_tree_aliases,
+};
+#[derive(Debug, Clone, PartialEq)]
+struct Scaled(u32);
+impl ComputableLeaf for Scaled {
+    type LeafContext = u32;
+    type LeafOutput = u32;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        self.0 * context
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Sum {
+    Sequential,
+    Parallel,
+}
+impl<BA, LA, BM, LM> ComputableBranch<Scaled, BA, LA, BM, LM> for Sum
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Scaled>, MaskType = LM>,
+    BM: Mask + Send + Sync,
+    LM: Mask + Sync,
+{
+    type BranchContext = u32;
+    type BranchOutput = u32;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Scaled>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Scaled, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        let leaves = controls.compute_all_leaves(context).sum::<u32>();
+        let branches = match self {
+            Self::Sequential => controls.compute_all_branches(context).sum::<u32>(),
+            Self::Parallel => controls.par_compute_all_branches(context).sum::<u32>(),
+        };
+        leaves + branches
+    }
+}
+make_tree_aliases!(SumTree, Sum, Scaled, u32, u64);
+
+fn build(kind: Sum) -> ConstructableTreeBranch<Sum, Scaled> {
+    let mut root = ConstructableTreeBranch::new(kind.clone()).with_leaf(Scaled(1));
+    for i in 0..6 {
+        let child = root.add_branch_mut(ConstructableTreeBranch::new(kind.clone()));
+        child.extend_leaves((0..i).map(Scaled));
+        child.add_branch(ConstructableTreeBranch::new(kind.clone()).with_leaf(Scaled(i)));
+    }
+    root
+}
+
+#[cfg(all(feature = "alloc-gen", feature = "rayon"))]
+#[test]
+fn parallel_matches_sequential() {
+    let sequential: SumTree = build(Sum::Sequential).to_tree().unwrap();
+    let parallel: SumTree = build(Sum::Parallel).to_tree().unwrap();
+    for context in 0..20 {
+        assert_eq!(parallel.compute(&context), sequential.compute(&context));
+    }
+    assert_eq!(parallel.compute(&1), 1 + 20 + 15);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Flag(bool);
+impl ComputableLeaf for Flag {
+    type LeafContext = bool;
+    type LeafOutput = bool;
+    fn compute(&self, context: &Self::LeafContext) -> Self::LeafOutput {
+        self.0 == *context
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Any {
+    Sequential,
+    Parallel,
+}
+impl<BA, LA, BM, LM> ComputableBranch<Flag, BA, LA, BM, LM> for Any
+where
+    BA: MaskTrackedArray<BranchNode<Self, BM, LM>, MaskType = BM>,
+    LA: MaskTrackedArray<LeafNode<Flag>, MaskType = LM>,
+    BM: Mask + Send + Sync,
+    LM: Mask + Sync,
+{
+    type BranchContext = bool;
+    type BranchOutput = bool;
+    fn compute<'a, H: ComputeHooks<Self::BranchOutput, Flag>>(
+        &self,
+        context: &Self::BranchContext,
+        controls: BranchControls<'a, Self, Flag, BA, LA, BM, LM, H>,
+    ) -> Self::BranchOutput {
+        let leaves = controls
+            .compute_all_leaves(context)
+            .fold(false, |a, b| a | b);
+        let branches = match self {
+            Self::Sequential => controls
+                .compute_all_branches(context)
+                .fold(false, |a, b| a | b),
+            Self::Parallel => controls
+                .par_compute_all_branches(context)
+                .reduce(|| false, |a, b| a | b),
+        };
+        leaves | branches
+    }
+}
+make_tree_aliases!(AnyTree, Any, Flag, u8, u16);
+
+fn build_flags(kind: Any) -> ConstructableTreeBranch<Any, Flag> {
+    let mut root = ConstructableTreeBranch::new(kind.clone()).with_leaf(Flag(false));
+    for i in 0..4 {
+        let child = root.add_branch_mut(ConstructableTreeBranch::new(kind.clone()));
+        child.extend_leaves((0..i).map(|j| Flag(j % 2 == 0)));
+        child.add_branch(ConstructableTreeBranch::new(kind.clone()).with_leaf(Flag(i % 2 == 1)));
+    }
+    root
+}
+
+#[cfg(all(feature = "alloc-gen", feature = "rayon"))]
+#[test]
+fn parallel_keeps_fired_mask() {
+    let sequential: AnyTree = build_flags(Any::Sequential).to_tree().unwrap();
+    let parallel: AnyTree = build_flags(Any::Parallel).to_tree().unwrap();
+    for context in [false, true] {
+        let (output, fired) = parallel.compute_with_fired_mask(&context);
+        assert_eq!(
+            (output, fired),
+            sequential.compute_with_fired_mask(&context)
+        );
+        assert!(output);
+        assert_ne!(fired, 0);
+        assert_eq!(parallel.compute(&context), output);
+    }
+}